@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The values accepted by the output option.
+pub const OUTPUT_VALUES: &[&str] = &["none", "summary", "compact", "default"];
+
+/// The values accepted by the mode option.
+pub const MODE_VALUES: &[&str] = &["move", "copy"];
+
+/// The values accepted by the time zone option.
+pub const TIME_ZONE_VALUES: &[&str] = &["local", "utc"];
+
+/// Command line arguments. Any value given here overrides the value in the configuration file.
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "photiso", about = "A very fast photo organizer.")]
+pub struct Args {
+    /// The configuration file to load. Defaults to ./photiso.toml when it exists.
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// The directory containing the photos that need to be organized.
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    pub unorganized: Option<PathBuf>,
+
+    /// The directory where organized photos should be placed.
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    pub organized: Option<PathBuf>,
+
+    /// The directory where exact duplicate photos should be placed.
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    pub duplicates: Option<PathBuf>,
+
+    /// The directory where photos without a plausible date should be placed. Defaults to undated in the organized
+    /// directory.
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    pub undated: Option<PathBuf>,
+
+    /// How much progress is written to the console.
+    #[structopt(long, possible_values = OUTPUT_VALUES)]
+    pub output: Option<String>,
+
+    /// Whether photos are moved or copied into the organized directory.
+    #[structopt(long, possible_values = MODE_VALUES)]
+    pub mode: Option<String>,
+
+    /// Whether folder and file names use the local time the photo was taken or UTC.
+    #[structopt(long, possible_values = TIME_ZONE_VALUES)]
+    pub time_zone: Option<String>,
+
+    /// The number of files read and hashed in parallel. Use 0 for one per CPU.
+    #[structopt(long, short = "j", value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Stop organizing at the first file that cannot be processed.
+    #[structopt(long)]
+    pub stop_on_error: bool,
+
+    /// Keep organizing when a file cannot be processed.
+    #[structopt(long, conflicts_with = "stop-on-error")]
+    pub no_stop_on_error: bool,
+
+    /// Report every move that would be made without touching the disk.
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Move (or copy) the photos, even when the configuration file sets a dry run.
+    #[structopt(long, conflicts_with = "dry-run")]
+    pub no_dry_run: bool,
+
+    /// Give photos whose extension does not match their contents the usual extension of their format.
+    #[structopt(long)]
+    pub fix_extensions: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands other than organizing photos.
+#[derive(Clone, Debug, StructOpt)]
+pub enum Command {
+    /// Moves the files of a previous run back to where they came from, using the journal in the organized directory.
+    Undo {
+        /// The id of the run to undo (shown when a run finishes).
+        run_id: String,
+    },
+    /// Shows every date-time a photo may have been taken, which one is used and why, and where the photo would be
+    /// organized. Nothing is moved.
+    Inspect {
+        /// The photos to inspect.
+        #[structopt(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+
+        /// Write the inspections as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+impl Args {
+    /// The stop on error override, if either flag was given.
+    pub fn stop_on_error(&self) -> Option<bool> {
+        if self.stop_on_error {
+            Some(true)
+        } else if self.no_stop_on_error {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// The dry run override, if either flag was given.
+    pub fn dry_run(&self) -> Option<bool> {
+        if self.dry_run {
+            Some(true)
+        } else if self.no_dry_run {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A box (also known as an atom) in an ISO base media file (i.e. QuickTime, MP4, HEIF, CR3).
+#[derive(Clone, Copy, Debug)]
+pub struct BmffBox {
+    /// The four character type of the box (e.g. `moov`)
+    pub box_type: [u8; 4],
+    /// The offset of the contents of the box, after its header
+    pub offset: u64,
+    /// The size of the contents of the box, without its header
+    pub size: u64,
+}
+
+impl BmffBox {
+    /// The offset of the end of the box.
+    pub fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+/// The boxes that a QuickTime or MP4 file can start with.
+const FIRST_BOX_TYPES: &[&[u8; 4]] = &[b"ftyp", b"moov", b"mdat", b"wide", b"free", b"skip"];
+
+/// Determines if the file looks like an ISO base media file by the type of its first box.
+pub fn is_bmff<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let mut header = [0u8; 8];
+    reader.seek(SeekFrom::Start(0))?;
+    if reader.read_exact(&mut header).is_err() {
+        return Ok(false);
+    }
+
+    Ok(FIRST_BOX_TYPES
+        .iter()
+        .any(|box_type| header[4..8] == box_type[..]))
+}
+
+/// Reads the headers of the boxes between `start` and `end`. The contents of the boxes are not read.
+pub fn read_boxes<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> io::Result<Vec<BmffBox>> {
+    let mut boxes = Vec::new();
+    let mut offset = start;
+
+    while offset + 8 <= end {
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let box_type = [header[4], header[5], header[6], header[7]];
+
+        let (header_size, box_size) = match size {
+            // the box extends to the end
+            0 => (8, end - offset),
+            // the size is a 64-bit value after the type
+            1 => {
+                let mut large_size = [0u8; 8];
+                reader.read_exact(&mut large_size)?;
+                (16, u64::from_be_bytes(large_size))
+            }
+            size => (8, size),
+        };
+
+        // a box whose size overflows is corrupt, and nothing after it can be found
+        let box_end = offset.checked_add(box_size).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The size of the box at {} overflows.", offset),
+            )
+        })?;
+
+        // a box that is smaller than its header, or larger than its parent, is corrupt
+        if box_size < header_size || box_end > end {
+            break;
+        }
+
+        boxes.push(BmffBox {
+            box_type,
+            offset: offset + header_size,
+            size: box_size - header_size,
+        });
+        offset = box_end;
+    }
+
+    Ok(boxes)
+}
+
+/// Finds the first box of a type between `start` and `end`.
+pub fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    box_type: &[u8; 4],
+) -> io::Result<Option<BmffBox>> {
+    Ok(read_boxes(reader, start, end)?
+        .into_iter()
+        .find(|b| &b.box_type == box_type))
+}
+
+/// Finds a box by its path of types (e.g. `moov/udta`) within the parent.
+pub fn find_box_path<R: Read + Seek>(
+    reader: &mut R,
+    parent: &BmffBox,
+    path: &[&[u8; 4]],
+) -> io::Result<Option<BmffBox>> {
+    let mut current = *parent;
+    for box_type in path {
+        match find_box(reader, current.offset, current.end(), box_type)? {
+            Some(child) => current = child,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(current))
+}
+
+/// Reads the contents of a box, up to `max` bytes.
+pub fn read_box_contents<R: Read + Seek>(
+    reader: &mut R,
+    bmff_box: &BmffBox,
+    max: u64,
+) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(bmff_box.offset))?;
+
+    let mut contents = Vec::new();
+    reader
+        .take(bmff_box.size.min(max))
+        .read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
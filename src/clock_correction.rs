@@ -0,0 +1,165 @@
+use crate::photo_date_time::*;
+use crate::photo_info::*;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::fmt;
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// A shift applied to the EXIF date-times of the photos taken by a camera whose clock was set wrong.
+#[derive(Clone, Debug)]
+pub struct ClockCorrection {
+    /// The camera manufacturer to match, if any
+    pub make: Option<String>,
+    /// The camera model to match, if any
+    pub model: Option<String>,
+    /// The camera body serial number to match, if any
+    pub serial_number: Option<String>,
+    /// The earliest camera date-time to match (inclusive), if any
+    pub from: Option<NaiveDateTime>,
+    /// The latest camera date-time to match (exclusive), if any
+    pub to: Option<NaiveDateTime>,
+    /// The duration added to the camera date-times
+    pub shift: Duration,
+}
+
+impl ClockCorrection {
+    /// Creates a clock correction from the text of its values, as written in the configuration file.
+    ///
+    /// The shift is `[+|-]HH:MM[:SS]`. The range is `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`; a `to` date without a time
+    /// includes the whole day.
+    pub fn parse(
+        make: Option<String>,
+        model: Option<String>,
+        serial_number: Option<String>,
+        from: Option<&str>,
+        to: Option<&str>,
+        shift: &str,
+    ) -> anyhow::Result<ClockCorrection> {
+        ensure!(
+            make.is_some() || model.is_some() || serial_number.is_some(),
+            "A clock correction must match a make, model, or serialNumber."
+        );
+
+        let from = match from {
+            Some(from) => Some(parse_range_date_time(from, false)?),
+            None => None,
+        };
+        let to = match to {
+            Some(to) => Some(parse_range_date_time(to, true)?),
+            None => None,
+        };
+
+        Ok(ClockCorrection {
+            make,
+            model,
+            serial_number,
+            from,
+            to,
+            shift: parse_shift(shift)?,
+        })
+    }
+
+    /// Determines if the correction applies to a photo taken by the camera at the date-time on its clock.
+    pub fn matches(&self, camera: &CameraInfo, date_time: &PhotoDateTime) -> bool {
+        is_match(&self.make, &camera.make)
+            && is_match(&self.model, &camera.model)
+            && is_match(&self.serial_number, &camera.serial_number)
+            && self.from.is_none_or(|from| date_time.local >= from)
+            && self.to.is_none_or(|to| date_time.local < to)
+    }
+}
+
+/// Formats the shift, followed by the camera it applies to (e.g. `+01:13:00 for Canon EOS R5`).
+impl fmt::Display for ClockCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.shift.num_seconds();
+        let sign = if seconds < 0 { '-' } else { '+' };
+        let seconds = seconds.abs();
+        write!(
+            f,
+            "{}{:02}:{:02}:{:02}",
+            sign,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+
+        let camera: Vec<&str> = [&self.make, &self.model, &self.serial_number]
+            .iter()
+            .filter_map(|value| value.as_deref())
+            .collect();
+        write!(f, " for {}", camera.join(" "))
+    }
+}
+
+/// Finds the first correction that applies to a photo.
+pub fn find_clock_correction<'a>(
+    corrections: &'a [ClockCorrection],
+    camera: &CameraInfo,
+    date_time: &PhotoDateTime,
+) -> Option<&'a ClockCorrection> {
+    corrections
+        .iter()
+        .find(|correction| correction.matches(camera, date_time))
+}
+
+/// Camera values are compared ignoring case and surrounding whitespace.
+#[doc(hidden)]
+fn is_match(expected: &Option<String>, actual: &Option<String>) -> bool {
+    match (expected, actual) {
+        (None, _) => true,
+        (Some(expected), Some(actual)) => expected.trim().eq_ignore_ascii_case(actual.trim()),
+        (Some(_), None) => false,
+    }
+}
+
+#[doc(hidden)]
+fn parse_shift(text: &str) -> anyhow::Result<Duration> {
+    let (sign, value) = match text.trim() {
+        value if value.starts_with('-') => (-1, &value[1..]),
+        value if value.starts_with('+') => (1, &value[1..]),
+        value => (1, value),
+    };
+
+    let parts = value
+        .split(':')
+        .map(|part| part.parse::<u32>().map(i64::from))
+        .collect::<Result<Vec<_>, _>>();
+
+    match parts.as_deref() {
+        Ok([hours, minutes]) if *minutes < 60 => {
+            Ok(Duration::minutes(sign * (hours * 60 + minutes)))
+        }
+        Ok([hours, minutes, seconds]) if *minutes < 60 && *seconds < 60 => Ok(Duration::seconds(
+            sign * (hours * 3600 + minutes * 60 + seconds),
+        )),
+        _ => bail!(
+            "The clock correction shift '{}' is not valid. Use [+|-]HH:MM[:SS] (e.g. +01:13:00).",
+            text
+        ),
+    }
+}
+
+/// Parses the date-time of one end of a range, as `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`. The end of a range given as a
+/// date (i.e. `is_end` is true) includes that whole day.
+pub fn parse_range_date_time(text: &str, is_end: bool) -> anyhow::Result<NaiveDateTime> {
+    let text = text.trim();
+
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S") {
+        return Ok(date_time);
+    }
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(date_time);
+    }
+
+    match NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        // the end of a range given as a date includes that whole day
+        Ok(date) if is_end => Ok(date.and_hms(0, 0, 0) + Duration::days(1)),
+        Ok(date) => Ok(date.and_hms(0, 0, 0)),
+        Err(_) => bail!(
+            "The date '{}' is not valid. Use YYYY-MM-DD or YYYY-MM-DD HH:MM:SS.",
+            text
+        ),
+    }
+}
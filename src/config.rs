@@ -1,37 +1,429 @@
+use crate::args::*;
+use crate::clock_correction::*;
+use crate::file_name_date_time::*;
+use crate::path_template::*;
+use crate::photo_date_time::*;
+use crate::photo_organizer::{DEFAULT_SIDECAR_EXTENSIONS, UNDATED_DIR_NAME};
 use serde::Deserialize;
-use std::{fs::File, io, io::Read, path::Path, path::PathBuf};
+use std::{fs::File, io::Read, path::Path, path::PathBuf};
 
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// The configuration file used when one is not given on the command line.
+const DEFAULT_CONFIG_PATH: &str = "./photiso.toml";
+
+#[derive(Clone, Debug)]
 pub struct ConfigDirectories {
     pub unorganized: PathBuf,
     pub organized: PathBuf,
     pub duplicates: PathBuf,
+    pub undated: PathBuf,
 }
 
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug)]
 pub struct ConfigOptions {
     pub output: String,
+    pub mode: String,
+    pub time_zone: String,
+    pub jobs: usize,
     pub stop_on_error: bool,
+    pub dry_run: bool,
+    /// Whether photos whose extension does not match their contents are given the usual extension of their format.
+    pub fix_extensions: bool,
+    /// The extensions (lower case, without the `.`) of the sidecar files that follow their photo.
+    pub sidecar_extensions: Vec<String>,
 }
 
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug)]
+pub struct ConfigTemplates {
+    pub organized: PathTemplate,
+    pub duplicates: PathTemplate,
+}
+
+#[derive(Clone, Debug)]
 pub struct Config {
+    /// The configuration file that was loaded, if any.
+    pub path: Option<PathBuf>,
     pub directories: ConfigDirectories,
     pub options: ConfigOptions,
+    pub templates: ConfigTemplates,
+    /// The corrections for cameras whose clocks were set wrong, in the order they are matched.
+    pub clock_corrections: Vec<ClockCorrection>,
+    /// How the date-time a photo was taken is found.
+    pub dates: DateOptions,
 }
 
-pub fn load_config() -> io::Result<Config> {
-    let path = Path::new("./photiso.toml");
+// -------------------- Configuration file -------------------- //
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfigDirectories {
+    unorganized: Option<PathBuf>,
+    organized: Option<PathBuf>,
+    duplicates: Option<PathBuf>,
+    undated: Option<PathBuf>,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfigOptions {
+    output: Option<String>,
+    mode: Option<String>,
+    time_zone: Option<String>,
+    jobs: Option<usize>,
+    stop_on_error: Option<bool>,
+    dry_run: Option<bool>,
+    fix_extensions: Option<bool>,
+    sidecar_extensions: Option<Vec<String>>,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfigTemplates {
+    organized: Option<String>,
+    duplicates: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfigClockCorrection {
+    make: Option<String>,
+    model: Option<String>,
+    serial_number: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    shift: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfigDates {
+    file_name_patterns: Option<Vec<String>>,
+    precedence: Option<Vec<String>>,
+    earliest: Option<String>,
+    latest: Option<String>,
+    bogus_date_times: Option<Vec<String>>,
+    fallback_to_file_times: Option<bool>,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct FileConfig {
+    #[serde(default)]
+    directories: FileConfigDirectories,
+    #[serde(default)]
+    options: FileConfigOptions,
+    #[serde(default)]
+    templates: FileConfigTemplates,
+    #[serde(default)]
+    clock_corrections: Vec<FileConfigClockCorrection>,
+    #[serde(default)]
+    dates: FileConfigDates,
+}
+
+/// Loads the configuration, layering the command line arguments over the configuration file.
+///
+/// The configuration file is `--config` when given; otherwise `./photiso.toml` if it exists.
+pub fn load_config(args: &Args) -> anyhow::Result<Config> {
+    let path = match &args.config {
+        Some(path) => Some(path.clone()),
+        None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists()),
+    };
+
+    let file_config = match &path {
+        Some(path) => read_config_file(path)?,
+        None => FileConfig::default(),
+    };
+
+    let organized = required_directory(
+        "organized",
+        &args.organized,
+        &file_config.directories.organized,
+        &path,
+    )?;
+
+    let directories = ConfigDirectories {
+        unorganized: required_directory(
+            "unorganized",
+            &args.unorganized,
+            &file_config.directories.unorganized,
+            &path,
+        )?,
+        duplicates: required_directory(
+            "duplicates",
+            &args.duplicates,
+            &file_config.directories.duplicates,
+            &path,
+        )?,
+        undated: args
+            .undated
+            .clone()
+            .or(file_config.directories.undated)
+            .unwrap_or_else(|| organized.join(UNDATED_DIR_NAME)),
+        organized,
+    };
 
-    let mut file = File::open(&path)?;
+    let output = args
+        .output
+        .clone()
+        .or(file_config.options.output)
+        .unwrap_or_else(|| String::from("default"));
+    ensure!(
+        OUTPUT_VALUES.contains(&output.as_str()),
+        "The output option '{}' is not valid. Use one of: {}.",
+        output,
+        OUTPUT_VALUES.join(", ")
+    );
+
+    let mode = args
+        .mode
+        .clone()
+        .or(file_config.options.mode)
+        .unwrap_or_else(|| String::from("move"));
+    ensure!(
+        MODE_VALUES.contains(&mode.as_str()),
+        "The mode option '{}' is not valid. Use one of: {}.",
+        mode,
+        MODE_VALUES.join(", ")
+    );
+
+    let time_zone = args
+        .time_zone
+        .clone()
+        .or(file_config.options.time_zone)
+        .unwrap_or_else(|| String::from("local"));
+    ensure!(
+        TIME_ZONE_VALUES.contains(&time_zone.as_str()),
+        "The time zone option '{}' is not valid. Use one of: {}.",
+        time_zone,
+        TIME_ZONE_VALUES.join(", ")
+    );
+
+    let jobs = args.jobs.or(file_config.options.jobs).unwrap_or(0);
+
+    let stop_on_error = args
+        .stop_on_error()
+        .or(file_config.options.stop_on_error)
+        .unwrap_or(false);
+
+    let dry_run = args
+        .dry_run()
+        .or(file_config.options.dry_run)
+        .unwrap_or(false);
+
+    let fix_extensions = args.fix_extensions || file_config.options.fix_extensions.unwrap_or(false);
+
+    let sidecar_extensions = match file_config.options.sidecar_extensions {
+        Some(extensions) => extensions
+            .iter()
+            .map(|extension| sidecar_extension(extension))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => DEFAULT_SIDECAR_EXTENSIONS
+            .iter()
+            .map(|extension| extension.to_string())
+            .collect(),
+    };
+
+    let templates = ConfigTemplates {
+        organized: template(
+            TemplateKind::Organized,
+            &file_config.templates.organized,
+            &path,
+        )?,
+        duplicates: template(
+            TemplateKind::Duplicates,
+            &file_config.templates.duplicates,
+            &path,
+        )?,
+    };
+
+    let clock_corrections = file_config
+        .clock_corrections
+        .into_iter()
+        .enumerate()
+        .map(|(index, correction)| {
+            ClockCorrection::parse(
+                correction.make,
+                correction.model,
+                correction.serial_number,
+                correction.from.as_deref(),
+                correction.to.as_deref(),
+                &correction.shift,
+            )
+            .with_context(|| format!("Clock correction {} is not valid.", index + 1))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let dates = dates(&file_config.dates)?;
+
+    Ok(Config {
+        path,
+        directories,
+        options: ConfigOptions {
+            output,
+            mode,
+            time_zone,
+            jobs,
+            stop_on_error,
+            dry_run,
+            fix_extensions,
+            sidecar_extensions,
+        },
+        templates,
+        clock_corrections,
+        dates,
+    })
+}
+
+#[doc(hidden)]
+fn read_config_file(path: &Path) -> anyhow::Result<FileConfig> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Unable to open the configuration file {:?}.", path))?;
 
     let mut s = String::new();
     file.read_to_string(&mut s)?;
 
-    Ok(toml::from_str(&s)?)
+    toml::from_str(&s).with_context(|| format!("Unable to read the configuration file {:?}.", path))
+}
+
+#[doc(hidden)]
+fn required_directory(
+    name: &str,
+    arg: &Option<PathBuf>,
+    file_value: &Option<PathBuf>,
+    config_path: &Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    if let Some(dir) = arg.as_ref().or(file_value.as_ref()) {
+        return Ok(dir.clone());
+    }
+
+    match config_path {
+        Some(config_path) => bail!(
+            "The {} directory is required. Set directories.{} in {:?} or pass --{} <dir>.",
+            name,
+            name,
+            config_path,
+            name
+        ),
+        None => bail!(
+            "The {} directory is required. No configuration file was found at {:?}, so pass --{} <dir> or --config <path>.",
+            name,
+            DEFAULT_CONFIG_PATH,
+            name
+        ),
+    }
+}
+
+#[doc(hidden)]
+fn template(
+    kind: TemplateKind,
+    file_value: &Option<String>,
+    config_path: &Option<PathBuf>,
+) -> anyhow::Result<PathTemplate> {
+    match (file_value, config_path) {
+        (Some(text), Some(config_path)) => PathTemplate::parse(text, kind).with_context(|| {
+            format!("The templates.{} value in {:?} is not valid.", kind, config_path)
+        }),
+        _ => Ok(PathTemplate::default_for(kind)),
+    }
+}
+
+/// The configured file name patterns are tried before the built-in patterns. Each date source may only be listed once
+/// in the precedence; sources that are not listed are not used. The configured bogus date-times replace the defaults.
+/// When file times are not a fallback they are left out of the default precedence, and cannot be listed.
+#[doc(hidden)]
+fn dates(file_dates: &FileConfigDates) -> anyhow::Result<DateOptions> {
+    let mut file_name_patterns = file_dates
+        .file_name_patterns
+        .iter()
+        .flatten()
+        .map(|text| {
+            FileNamePattern::parse(text).context("A dates.fileNamePatterns value is not valid.")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    file_name_patterns.extend(FileNamePattern::built_in());
+
+    let fallback_to_file_times = file_dates.fallback_to_file_times.unwrap_or(true);
+
+    let precedence = match &file_dates.precedence {
+        Some(names) => {
+            let mut precedence = Vec::new();
+            for name in names {
+                let source =
+                    DateSource::parse(name).context("A dates.precedence value is not valid.")?;
+                ensure!(
+                    !precedence.contains(&source),
+                    "The date source '{}' is in dates.precedence more than once.",
+                    name
+                );
+                precedence.push(source);
+            }
+            ensure!(
+                fallback_to_file_times || !precedence.contains(&DateSource::FileTimes),
+                "The dates.precedence value cannot include fileTimes when dates.fallbackToFileTimes is false."
+            );
+            precedence
+        }
+        None => DEFAULT_PRECEDENCE
+            .iter()
+            .copied()
+            .filter(|source| fallback_to_file_times || *source != DateSource::FileTimes)
+            .collect(),
+    };
+
+    let earliest = match &file_dates.earliest {
+        Some(text) => {
+            parse_range_date_time(text, false).context("The dates.earliest value is not valid.")?
+        }
+        None => DateOptions::default().earliest,
+    };
+
+    let latest = match &file_dates.latest {
+        Some(text) => Some(
+            parse_range_date_time(text, true).context("The dates.latest value is not valid.")?,
+        ),
+        None => None,
+    };
+    if let Some(latest) = latest {
+        ensure!(
+            earliest < latest,
+            "The dates.earliest value must be before the dates.latest value."
+        );
+    }
+
+    let bogus_date_times = match &file_dates.bogus_date_times {
+        Some(texts) => texts
+            .iter()
+            .map(|text| {
+                parse_range_date_time(text, false)
+                    .context("A dates.bogusDateTimes value is not valid.")
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => default_bogus_date_times(),
+    };
+
+    Ok(DateOptions {
+        file_name_patterns,
+        precedence,
+        earliest,
+        latest,
+        bogus_date_times,
+        fallback_to_file_times,
+    })
+}
+
+/// Sidecar extensions are compared in lower case, and may be written with a leading `.`.
+#[doc(hidden)]
+fn sidecar_extension(text: &str) -> anyhow::Result<String> {
+    let extension = text.trim().trim_start_matches('.').to_lowercase();
+    ensure!(
+        !extension.is_empty() && !extension.contains(['/', '\\', '.']),
+        "The sidecar extension '{}' is not valid. Use an extension such as xmp.",
+        text
+    );
+
+    Ok(extension)
 }
 
 // impl Clone for ConfigOptions {
@@ -0,0 +1,154 @@
+use crate::file_hash::*;
+use std::{
+    fmt, fs,
+    fs::{File, FileTimes, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The error returned when a copied file does not have the same contents as the original.
+#[derive(Debug)]
+pub struct VerificationError {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub expected_hash: String,
+    pub actual_hash: String,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The copy of {:?} at {:?} does not match the original (expected hash {}, found {}).",
+            self.from, self.to, self.expected_hash, self.actual_hash
+        )
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+/// Moves a file, creating the destination directory if needed.
+///
+/// A file cannot be renamed across file systems (e.g. from a USB drive to a NAS), so in that case the file is
+/// copied, flushed, and verified before the original is removed. If the copy cannot be verified, a
+/// `VerificationError` is returned and the original is left in place. If the original cannot be removed (e.g. it is
+/// on a read-only card), the copy is removed and the error is returned.
+pub fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(to_dir) = to.parent() {
+        fs::create_dir_all(to_dir)?;
+    }
+
+    match fs::rename(from, to) {
+        Ok(_) => {}
+        Err(err) if is_cross_device_error(&err) => {
+            copy_file(from, to)?;
+
+            // a copy left behind would not be journaled, and would be mistaken for the photo on the next run
+            if let Err(err) = fs::remove_file(from) {
+                let _ = fs::remove_file(to);
+                return Err(err.into());
+            }
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    Ok(())
+}
+
+/// Moves a file without ever replacing a file at the destination, creating the destination directory if needed.
+///
+/// A rename replaces an existing file, so the file is hard linked to the destination and then unlinked from its
+/// original location. When it cannot be linked (e.g. across file systems), it is copied and verified like
+/// `move_file`. An `io::ErrorKind::AlreadyExists` error is returned when a file is at the destination.
+pub fn move_file_no_clobber(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(to_dir) = to.parent() {
+        fs::create_dir_all(to_dir)?;
+    }
+
+    match fs::hard_link(from, to) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Err(err.into()),
+        // the copy is created with create_new, so it fails the same way when a file is at the destination
+        Err(_) => {
+            copy_file(from, to)?;
+        }
+    }
+
+    // the file is at both locations until the original is removed, so remove the new one if it cannot be
+    if let Err(err) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Determines if an error is because a file is already at the destination.
+pub fn is_already_exists_error(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<io::Error>(), Some(err) if err.kind() == io::ErrorKind::AlreadyExists)
+}
+
+/// Copies a file, creating the destination directory if needed, and returns the SHA256 hash of the file.
+///
+/// The copy never overwrites an existing file. It is flushed to disk, given the timestamps and permissions of the
+/// original, and verified against the hash of the original. If any step fails the partial copy is removed, and if the
+/// copy does not match a `VerificationError` is returned; the original is never touched.
+pub fn copy_file(from: &Path, to: &Path) -> anyhow::Result<String> {
+    if let Some(to_dir) = to.parent() {
+        fs::create_dir_all(to_dir)?;
+    }
+
+    let expected_hash = get_file_hash(from)?;
+    let metadata = fs::metadata(from)?;
+
+    let mut source = File::open(from)?;
+    let mut dest = OpenOptions::new().write(true).create_new(true).open(to)?;
+
+    // the destination is new, so a partial copy can be removed whatever fails
+    let result = (move || -> anyhow::Result<String> {
+        io::copy(&mut source, &mut dest)?;
+
+        dest.set_times(
+            FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?),
+        )?;
+        dest.sync_all()?;
+        drop(dest);
+
+        fs::set_permissions(to, metadata.permissions())?;
+
+        let actual_hash = get_file_hash(to)?;
+        if actual_hash != expected_hash {
+            return Err(VerificationError {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                expected_hash,
+                actual_hash,
+            }
+            .into());
+        }
+
+        Ok(expected_hash)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(to);
+    }
+
+    result
+}
+
+/// Determines if the error is because a file cannot be renamed to a different file system.
+#[doc(hidden)]
+fn is_cross_device_error(err: &io::Error) -> bool {
+    // EXDEV on unix, ERROR_NOT_SAME_DEVICE on windows
+    #[cfg(unix)]
+    const CROSS_DEVICE_ERROR: i32 = 18;
+    #[cfg(windows)]
+    const CROSS_DEVICE_ERROR: i32 = 17;
+    #[cfg(not(any(unix, windows)))]
+    const CROSS_DEVICE_ERROR: i32 = -1;
+
+    err.raw_os_error() == Some(CROSS_DEVICE_ERROR)
+}
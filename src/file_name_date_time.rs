@@ -0,0 +1,176 @@
+use crate::photo_date_time::*;
+use chrono::NaiveDate;
+use regex::{Captures, Regex};
+use std::{fmt, path::Path};
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// The patterns of the dates that phones and apps put in file names, tried after the configured patterns.
+///
+/// * `IMG_20190714_153012.jpg`, `VID_20190714_153012.mp4`, `PXL_20210101_101010123.jpg`, `20190714_153012.jpg`
+/// * `Screenshot_2020-05-01-10-00-00.png`, `2020-05-01 10.00.00.jpg`
+/// * `IMG-20200101-WA0001.jpg` (WhatsApp, which has no time)
+const BUILT_IN_PATTERNS: &[&str] = &[
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})[_-](?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})(?P<fraction>\d{1,9})?(?:\D|$)",
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})-(?P<month>\d{2})-(?P<day>\d{2})[ _-](?P<hour>\d{2})[.:-](?P<minute>\d{2})[.:-](?P<second>\d{2})(?:\D|$)",
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})-WA\d+",
+];
+
+/// The named groups a pattern must have.
+const REQUIRED_GROUPS: &[&str] = &["year", "month", "day"];
+
+/// A regular expression that finds the date-time a photo was taken in its file name.
+///
+/// The pattern has the named groups `year`, `month`, and `day`, and optionally `hour`, `minute`, `second`, and
+/// `fraction` (the digits of the fraction of a second).
+#[derive(Clone, Debug)]
+pub struct FileNamePattern {
+    regex: Regex,
+}
+
+impl FileNamePattern {
+    /// Creates a pattern from a regular expression, checking it has the named groups for the date.
+    pub fn parse(text: &str) -> anyhow::Result<FileNamePattern> {
+        let regex = Regex::new(text)
+            .with_context(|| format!("The file name pattern '{}' is not valid.", text))?;
+
+        for group in REQUIRED_GROUPS {
+            ensure!(
+                regex.capture_names().any(|name| name == Some(*group)),
+                "The file name pattern '{}' does not have a (?P<{}>...) group.",
+                text,
+                group
+            );
+        }
+
+        Ok(FileNamePattern { regex })
+    }
+
+    /// The built-in patterns, for the names given by common phones and apps.
+    pub fn built_in() -> Vec<FileNamePattern> {
+        BUILT_IN_PATTERNS
+            .iter()
+            .map(|text| FileNamePattern::parse(text).unwrap())
+            .collect()
+    }
+
+    /// Finds the date-time in a file name, or None if it does not match or is not a valid date-time.
+    pub fn find(&self, file_name: &str) -> Option<PhotoDateTime> {
+        let captures = self.regex.captures(file_name)?;
+
+        let date = NaiveDate::from_ymd_opt(
+            number(&captures, "year")? as i32,
+            number(&captures, "month")?,
+            number(&captures, "day")?,
+        )?;
+
+        let nanos = match captures.name("fraction") {
+            Some(fraction) => fraction_nanos(fraction.as_str())?,
+            None => 0,
+        };
+        let local = date.and_hms_nano_opt(
+            optional_number(&captures, "hour")?,
+            optional_number(&captures, "minute")?,
+            optional_number(&captures, "second")?,
+            nanos,
+        )?;
+
+        // the name is the local time of the phone or app, which does not say its offset
+        Some(PhotoDateTime {
+            local,
+            offset: None,
+        })
+    }
+}
+
+impl fmt::Display for FileNamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.regex.as_str())
+    }
+}
+
+/// Reads the date-time in the name of a file, trying each pattern in order.
+pub fn read_file_name_date_time(
+    file_path: &Path,
+    patterns: &[FileNamePattern],
+) -> Option<PhotoDateTime> {
+    let file_name = file_path.file_name()?.to_str()?;
+
+    patterns.iter().find_map(|pattern| pattern.find(file_name))
+}
+
+#[doc(hidden)]
+fn number(captures: &Captures, group: &str) -> Option<u32> {
+    captures.name(group)?.as_str().parse().ok()
+}
+
+/// A missing time group is 0 (e.g. a name with a date but no time is midnight).
+#[doc(hidden)]
+fn optional_number(captures: &Captures, group: &str) -> Option<u32> {
+    match captures.name(group) {
+        Some(value) => value.as_str().parse().ok(),
+        None => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn find_built_in(file_name: &str) -> Option<NaiveDateTime> {
+        read_file_name_date_time(Path::new(file_name), &FileNamePattern::built_in())
+            .map(|date_time| date_time.local)
+    }
+
+    fn date_time(
+        (year, month, day): (i32, u32, u32),
+        (hour, minute, second, nanos): (u32, u32, u32, u32),
+    ) -> NaiveDateTime {
+        NaiveDate::from_ymd(year, month, day).and_hms_nano(hour, minute, second, nanos)
+    }
+
+    #[test]
+    fn built_in_patterns_find_phone_and_app_names() {
+        let cases = [
+            (
+                "IMG_20190714_153012.jpg",
+                date_time((2019, 7, 14), (15, 30, 12, 0)),
+            ),
+            (
+                "PXL_20210101_101010123.jpg",
+                date_time((2021, 1, 1), (10, 10, 10, 123_000_000)),
+            ),
+            (
+                "Screenshot_2020-05-01-10-00-00.png",
+                date_time((2020, 5, 1), (10, 0, 0, 0)),
+            ),
+            (
+                "IMG-20200101-WA0001.jpg",
+                date_time((2020, 1, 1), (0, 0, 0, 0)),
+            ),
+        ];
+
+        for (file_name, expected) in cases.iter() {
+            assert_eq!(find_built_in(file_name), Some(*expected), "{}", file_name);
+        }
+    }
+
+    #[test]
+    fn built_in_patterns_ignore_invalid_dates_and_long_digit_runs() {
+        let cases = [
+            "IMG_20191314_153012.jpg",
+            "IMG_20190732_153012.jpg",
+            "Screenshot_2020-13-01-10-00-00.png",
+            "IMG-20201301-WA0001.jpg",
+            "IMG_20190714_1530121234567890.jpg",
+            "12320190714_153012.jpg",
+            "120190714-WA0001.jpg",
+        ];
+
+        for file_name in cases.iter() {
+            assert_eq!(find_built_in(file_name), None, "{}", file_name);
+        }
+    }
+}
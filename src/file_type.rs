@@ -0,0 +1,312 @@
+use std::{fmt, fs::File, io::Read, path::Path};
+
+/// A photo or video format, recognized by the first bytes of the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileType {
+    /// The name of the format
+    pub name: &'static str,
+    /// The extensions (lower case, without the `.`) of the format, the usual one first
+    pub extensions: &'static [&'static str],
+    /// Whether the format is a video
+    pub is_video: bool,
+}
+
+impl FileType {
+    /// The usual extension of the format.
+    pub fn extension(&self) -> &'static str {
+        self.extensions[0]
+    }
+
+    /// Determines if an extension (lower case) is one of the format's extensions.
+    pub fn has_extension(&self, extension: &str) -> bool {
+        self.extensions.contains(&extension)
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[doc(hidden)]
+const fn photo(name: &'static str, extensions: &'static [&'static str]) -> FileType {
+    FileType {
+        name,
+        extensions,
+        is_video: false,
+    }
+}
+
+#[doc(hidden)]
+const fn video(name: &'static str, extensions: &'static [&'static str]) -> FileType {
+    FileType {
+        name,
+        extensions,
+        is_video: true,
+    }
+}
+
+pub const JPEG: FileType = photo("JPEG", &["jpg", "jpeg"]);
+pub const PNG: FileType = photo("PNG", &["png"]);
+pub const GIF: FileType = photo("GIF", &["gif"]);
+pub const BMP: FileType = photo("BMP", &["bmp"]);
+pub const WEBP: FileType = photo("WebP", &["webp"]);
+pub const JPEG_XR: FileType = photo("JPEG XR", &["wmp"]);
+pub const HEIF: FileType = photo("HEIF", &["heic", "heif", "hif"]);
+pub const AVIF: FileType = photo("AVIF", &["avif"]);
+/// TIFF files, and the RAW formats that are plain TIFF files (which cannot be told apart by their first bytes).
+pub const TIFF: FileType = photo(
+    "TIFF",
+    &[
+        "tif", "tiff", "dng", "nef", "nrw", "arw", "sr2", "pef", "srw", "3fr", "erf", "kdc", "mef",
+    ],
+);
+pub const CR2: FileType = photo("Canon CR2", &["cr2"]);
+pub const CR3: FileType = photo("Canon CR3", &["cr3"]);
+pub const RAF: FileType = photo("Fujifilm RAF", &["raf"]);
+pub const ORF: FileType = photo("Olympus ORF", &["orf"]);
+pub const RW2: FileType = photo("Panasonic RW2", &["rw2"]);
+pub const QUICKTIME: FileType = video("QuickTime", &["mov"]);
+pub const MP4: FileType = video("MP4", &["mp4", "m4v"]);
+pub const THREE_GP: FileType = video("3GP", &["3gp", "3g2"]);
+
+/// Every format that is organized.
+pub const FILE_TYPES: &[FileType] = &[
+    JPEG, PNG, GIF, BMP, WEBP, JPEG_XR, HEIF, AVIF, TIFF, CR2, CR3, RAF, ORF, RW2, QUICKTIME, MP4,
+    THREE_GP,
+];
+
+/// The number of bytes read to recognize a format. ISO base media files list their brands in the first box.
+const HEADER_LEN: usize = 64;
+
+/// The header of a Fujifilm RAF file.
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+
+/// The sizes of the DIB headers a BMP file may have (`BITMAPCOREHEADER` to `BITMAPV5HEADER`).
+const BMP_DIB_HEADER_SIZES: &[u32] = &[12, 40, 52, 56, 108, 124];
+
+/// The extensions (lower case) of common files that are never a photo or video, so they are not opened to recognize
+/// their format.
+const NON_PHOTO_EXTENSIONS: &[&str] = &[
+    "txt", "csv", "log", "md", "ini", "db", "pdf", "doc", "docx", "xls", "xlsx", "html", "htm",
+    "zip", "mp3", "m4a", "m4b", "wav", "flac",
+];
+
+/// The brands of ISO base media files that only say how the file is structured, so the compatible brands decide the
+/// format.
+const GENERIC_BRANDS: &[&[u8; 4]] = &[
+    b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"iso7", b"iso8", b"iso9", b"mif1",
+    b"msf1", b"miaf",
+];
+
+/// The boxes a QuickTime file from before the `ftyp` box may start with.
+const QUICKTIME_BOX_TYPES: &[&[u8; 4]] = &[b"moov", b"mdat", b"free", b"skip", b"wide", b"pnot"];
+
+/// Recognizes the format of a file by its first bytes, or None if it is not a photo or video format that is
+/// organized. The extension of the file is not used.
+pub fn detect_file_type(file_path: &Path) -> anyhow::Result<Option<FileType>> {
+    let file = File::open(file_path)?;
+    let len = file.metadata()?.len();
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    file.take(HEADER_LEN as u64).read_to_end(&mut header)?;
+
+    Ok(detect_header_file_type(&header, len))
+}
+
+/// Determines if an extension (lower case) is the extension of a common file that is never a photo or video.
+pub fn is_non_photo_extension(extension: &str) -> bool {
+    NON_PHOTO_EXTENSIONS.contains(&extension)
+}
+
+/// Determines if an extension (lower case) is the extension of a photo or video format that is organized.
+pub fn is_photo_extension(extension: &str) -> bool {
+    FILE_TYPES
+        .iter()
+        .any(|file_type| file_type.has_extension(extension))
+}
+
+#[doc(hidden)]
+fn detect_header_file_type(header: &[u8], len: u64) -> Option<FileType> {
+    let starts_with = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    match header {
+        _ if starts_with(&[0xFF, 0xD8, 0xFF]) => Some(JPEG),
+        _ if starts_with(b"\x89PNG\r\n\x1a\n") => Some(PNG),
+        _ if starts_with(b"GIF87a") || starts_with(b"GIF89a") => Some(GIF),
+        _ if starts_with(b"BM") && is_bmp_header(header, len) => Some(BMP),
+        _ if starts_with(b"RIFF") && at(8, b"WEBP") => Some(WEBP),
+        _ if starts_with(b"II\xBC\x01") => Some(JPEG_XR),
+        _ if starts_with(b"II*\0") && at(8, b"CR\x02") => Some(CR2),
+        _ if starts_with(b"II*\0") || starts_with(b"MM\0*") => Some(TIFF),
+        _ if starts_with(b"IIRO") || starts_with(b"IIRS") || starts_with(b"MMOR") => Some(ORF),
+        _ if starts_with(b"IIU\0") => Some(RW2),
+        _ if starts_with(RAF_MAGIC) => Some(RAF),
+        _ if at(4, b"ftyp") => detect_bmff_file_type(header),
+        _ if QUICKTIME_BOX_TYPES.iter().any(|box_type| at(4, *box_type)) => Some(QUICKTIME),
+        _ => None,
+    }
+}
+
+/// A BMP file starts with `BM`, the size of the file, 4 reserved bytes, the offset of the pixels, and then the DIB
+/// header, which starts with its own size. Two bytes are not enough to tell a BMP file from a text file.
+#[doc(hidden)]
+fn is_bmp_header(header: &[u8], len: u64) -> bool {
+    let read_u32 = |offset: usize| {
+        header
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    match (read_u32(2), read_u32(14)) {
+        (Some(size), Some(dib_header_size)) => {
+            size as u64 == len && BMP_DIB_HEADER_SIZES.contains(&dib_header_size)
+        }
+        _ => false,
+    }
+}
+
+/// ISO base media files (e.g. HEIF, AVIF, CR3, MP4, and QuickTime) start with an `ftyp` box: its size, `ftyp`, the
+/// major brand, a minor version, and then the compatible brands. The major brand decides the format, unless it is a
+/// generic brand (e.g. `isom` or `mif1`), in which case the compatible brands do.
+#[doc(hidden)]
+fn detect_bmff_file_type(header: &[u8]) -> Option<FileType> {
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let end = size.min(header.len());
+
+    let major = header.get(8..12)?;
+    if !GENERIC_BRANDS.iter().any(|brand| brand[..] == *major) {
+        return get_brand_file_type(major).flatten();
+    }
+
+    (16..end)
+        .step_by(4)
+        .filter_map(|offset| header.get(offset..offset + 4))
+        .find_map(get_brand_file_type)
+        .unwrap_or(match major {
+            // a still image (or image sequence) without a more specific brand
+            b"mif1" | b"msf1" | b"miaf" => Some(HEIF),
+            _ => Some(MP4),
+        })
+}
+
+/// The format of a brand, Some(None) for an audio brand (e.g. an `.m4a` song or `.m4b` audiobook), which is not
+/// organized, or None for a generic or unknown brand.
+#[doc(hidden)]
+fn get_brand_file_type(brand: &[u8]) -> Option<Option<FileType>> {
+    match brand {
+        b"avif" | b"avis" => Some(Some(AVIF)),
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => Some(Some(HEIF)),
+        b"crx " => Some(Some(CR3)),
+        b"qt  " => Some(Some(QUICKTIME)),
+        b"M4V " | b"M4VH" | b"M4VP" | b"mp41" | b"mp42" | b"avc1" => Some(Some(MP4)),
+        b"M4A " | b"M4B " | b"M4P " | b"F4A " | b"F4B " => Some(None),
+        _ if brand.starts_with(b"3gp") || brand.starts_with(b"3g2") => Some(Some(THREE_GP)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `ftyp` box with a major brand and compatible brands, padded to the length of a header.
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + compatible.len() * 4;
+        let mut header = (size as u32).to_be_bytes().to_vec();
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(major);
+        header.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            header.extend_from_slice(*brand);
+        }
+        header.resize(HEADER_LEN, 0);
+        header
+    }
+
+    /// A BMP header for a file of `len` bytes with a DIB header of `dib_header_size` bytes.
+    fn bmp(len: u32, dib_header_size: u32) -> Vec<u8> {
+        let mut header = b"BM".to_vec();
+        header.extend_from_slice(&len.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&54u32.to_le_bytes());
+        header.extend_from_slice(&dib_header_size.to_le_bytes());
+        header.resize(HEADER_LEN, 0);
+        header
+    }
+
+    fn detect(header: &[u8]) -> Option<&'static str> {
+        detect_header_file_type(header, 1000).map(|file_type| file_type.name)
+    }
+
+    #[test]
+    fn photo_formats_are_detected() {
+        assert_eq!(detect(b"\xFF\xD8\xFF\xE1"), Some("JPEG"));
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\0\0"), Some("PNG"));
+        assert_eq!(detect(b"GIF89a"), Some("GIF"));
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 "), Some("WebP"));
+        assert_eq!(detect(b"II*\0\x10\0\0\0CR\x02\0"), Some("Canon CR2"));
+        assert_eq!(detect(b"II*\0\x08\0\0\0"), Some("TIFF"));
+        assert_eq!(detect(b"MM\0*\0\0\0\x08"), Some("TIFF"));
+        assert_eq!(detect(b"IIRO\x08\0\0\0"), Some("Olympus ORF"));
+        assert_eq!(detect(b"IIU\0\x08\0\0\0"), Some("Panasonic RW2"));
+        assert_eq!(detect(b"FUJIFILMCCD-RAW 0201"), Some("Fujifilm RAF"));
+    }
+
+    #[test]
+    fn bmff_formats_are_detected_by_brand() {
+        assert_eq!(detect(&ftyp(b"heic", &[b"mif1", b"heic"])), Some("HEIF"));
+        assert_eq!(detect(&ftyp(b"mif1", &[b"mif1", b"heic"])), Some("HEIF"));
+        assert_eq!(detect(&ftyp(b"mif1", &[b"mif1", b"miaf"])), Some("HEIF"));
+        assert_eq!(detect(&ftyp(b"avif", &[b"mif1", b"miaf"])), Some("AVIF"));
+        assert_eq!(
+            detect(&ftyp(b"crx ", &[b"crx ", b"isom"])),
+            Some("Canon CR3")
+        );
+        assert_eq!(detect(&ftyp(b"qt  ", &[b"qt  "])), Some("QuickTime"));
+        assert_eq!(
+            detect(&ftyp(b"isom", &[b"isom", b"avc1", b"mp41"])),
+            Some("MP4")
+        );
+        assert_eq!(detect(&ftyp(b"3gp4", &[b"isom", b"3gp4"])), Some("3GP"));
+        assert_eq!(detect(&ftyp(b"3g2a", &[b"3g2a"])), Some("3GP"));
+    }
+
+    #[test]
+    fn audio_brands_are_not_detected() {
+        assert_eq!(detect(&ftyp(b"M4A ", &[b"M4A ", b"mp42", b"isom"])), None);
+        assert_eq!(detect(&ftyp(b"M4B ", &[b"M4B ", b"mp42", b"isom"])), None);
+        assert_eq!(detect(&ftyp(b"isom", &[b"isom", b"M4A "])), None);
+    }
+
+    #[test]
+    fn bmp_header_must_match_the_file() {
+        assert_eq!(detect_header_file_type(&bmp(1000, 40), 1000), Some(BMP));
+        assert_eq!(detect_header_file_type(&bmp(1000, 124), 1000), Some(BMP));
+        assert_eq!(detect_header_file_type(&bmp(999, 40), 1000), None);
+        assert_eq!(detect_header_file_type(&bmp(1000, 41), 1000), None);
+        assert_eq!(detect(b"BM text that is not a bitmap"), None);
+    }
+
+    #[test]
+    fn other_files_are_not_detected() {
+        assert_eq!(detect(b""), None);
+        assert_eq!(detect(b"hello, world"), None);
+        assert_eq!(detect(b"%PDF-1.7"), None);
+        assert_eq!(detect(b"PK\x03\x04"), None);
+    }
+
+    #[test]
+    fn raw_and_video_extensions_are_photo_extensions() {
+        for extension in [
+            "pef", "srw", "nrw", "sr2", "3fr", "erf", "kdc", "mef", "3g2",
+        ]
+        .iter()
+        {
+            assert!(is_photo_extension(extension), "{}", extension);
+        }
+        assert!(!is_photo_extension("txt"));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::file_type::*;
+use crate::photo_date_time::*;
+use crate::photo_info::*;
+use crate::photo_organizer::*;
+use crate::takeout::*;
+use crate::video_date_time::*;
+use crate::xmp_date_time::*;
+use chrono::NaiveDateTime;
+use exif::{In, Tag};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// How the date-time a photo was taken was decided, and where the photo would be organized.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inspection {
+    /// The photo that was inspected.
+    pub file: PathBuf,
+    /// The format of the photo, recognized by its contents.
+    pub file_type: String,
+    /// The SHA256 hash of the photo.
+    pub sha256: String,
+    /// The camera that took the photo.
+    pub camera: CameraInfo,
+    /// Every date-time the photo may have been taken: the sources that are tried in order, then the others.
+    pub dates: Vec<DateCandidate>,
+    /// The offset of the camera clock from UTC derived from the GPS time, if any.
+    pub gps_offset: Option<String>,
+    /// The clock correction applied to the date-times, if any.
+    pub clock_correction: Option<String>,
+    /// The source of the date-time that was used, or None when the photo is undated.
+    pub source: Option<String>,
+    /// Why the source was used.
+    pub reason: String,
+    /// Where the photo would be placed, before any conflict with an existing file.
+    pub destination: PathBuf,
+    /// Every EXIF field of the photo.
+    pub exif: Vec<ExifFieldText>,
+}
+
+/// The inspection of a photo, or why it could not be inspected.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum InspectionResult {
+    Inspected(Box<Inspection>),
+    Failed(InspectionFailure),
+}
+
+/// Why a photo could not be inspected.
+#[derive(Debug, Serialize)]
+pub struct InspectionFailure {
+    /// The photo that was inspected.
+    pub file: PathBuf,
+    /// The error, with its causes.
+    pub error: String,
+}
+
+/// A date-time a photo may have been taken, from one source.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DateCandidate {
+    /// The name of the source, as written in the configuration file.
+    pub source: String,
+    /// The position of the source in the order the sources are tried (1 based), or None if it is not tried.
+    pub rank: Option<usize>,
+    /// The local date-time, after any clock correction.
+    pub local: Option<NaiveDateTime>,
+    /// The offset of the local date-time from UTC, if it is known.
+    pub offset: Option<String>,
+    /// The date-time in UTC, if the offset is known.
+    pub utc: Option<DateTime<Utc>>,
+    /// Whether the date-time was used.
+    pub status: CandidateStatus,
+    /// Why a date-time is implausible or a source is not tried.
+    pub note: Option<String>,
+    /// The tags (or other values) the date-time was read from.
+    pub tags: Vec<RawTag>,
+}
+
+/// Whether a date-time was used for a photo.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CandidateStatus {
+    /// The date-time was used.
+    Chosen,
+    /// The date-time is plausible, but a source before it was used.
+    Outranked,
+    /// The photo does not have a date-time from the source.
+    Missing,
+    /// The photo could not have been taken at the date-time.
+    Implausible,
+    /// The source is not in the precedence.
+    NotTried,
+}
+
+/// A raw value a date-time was read from.
+#[derive(Debug, Serialize)]
+pub struct RawTag {
+    pub name: String,
+    pub value: String,
+}
+
+/// An EXIF field, as text.
+#[derive(Debug, Serialize)]
+pub struct ExifFieldText {
+    pub tag: String,
+    pub ifd: String,
+    pub value: String,
+}
+
+/// Inspects a photo, keeping the error when it cannot be inspected so the other photos can still be inspected.
+pub fn inspect_result(
+    file_path: &Path,
+    unorganized_dir: &Path,
+    organized_dir: &Path,
+    undated_dir: &Path,
+    options: &OrganizeOptions,
+) -> InspectionResult {
+    match inspect(
+        file_path,
+        unorganized_dir,
+        organized_dir,
+        undated_dir,
+        options,
+    ) {
+        Ok(inspection) => InspectionResult::Inspected(Box::new(inspection)),
+        Err(err) => InspectionResult::Failed(InspectionFailure {
+            file: file_path.to_path_buf(),
+            error: format!("{:#}", err),
+        }),
+    }
+}
+
+/// Inspects a photo: reads every date-time it may have been taken, decides which is used the same way `organize`
+/// does, and works out where it would be placed.
+///
+/// # Arguments
+///
+/// * `file_path` - The photo to inspect.
+/// * `unorganized_dir` - The directory containing the photos that need to be organized.
+/// * `organized_dir` - The directory where organized photos are placed.
+/// * `undated_dir` - The directory where photos without a plausible date-time are placed.
+/// * `options` - The options photos are organized with.
+///
+/// Nothing is moved or written.
+pub fn inspect(
+    file_path: &Path,
+    unorganized_dir: &Path,
+    organized_dir: &Path,
+    undated_dir: &Path,
+    options: &OrganizeOptions,
+) -> anyhow::Result<Inspection> {
+    ensure!(
+        file_path.is_file(),
+        "The file '{}' does not exist.",
+        file_path.display()
+    );
+
+    let planned = plan_photo(
+        file_path,
+        unorganized_dir,
+        organized_dir,
+        undated_dir,
+        options,
+    )?;
+
+    let exif = read_exif(file_path)?;
+    let date_time_info = &planned.info.date_time_info;
+    let chosen = planned.source;
+
+    // the sources that are tried come first, in order, followed by the others
+    let sources = options.dates.sources();
+    let chosen_rank = chosen.and_then(|chosen| sources.iter().position(|source| *source == chosen));
+    let others = DEFAULT_PRECEDENCE
+        .iter()
+        .filter(|source| !sources.contains(source));
+
+    let mut implausible = Vec::new();
+    let mut dates = Vec::new();
+    for source in sources.iter().chain(others) {
+        let date_time = date_time_info.get(*source);
+        let rank = sources.iter().position(|tried| tried == source);
+
+        let (status, note) = match (rank, &date_time) {
+            (None, _) => (
+                CandidateStatus::NotTried,
+                Some("not in the precedence".to_string()),
+            ),
+            (Some(_), None) => (CandidateStatus::Missing, None),
+            (Some(_), Some(_)) if chosen == Some(*source) => (CandidateStatus::Chosen, None),
+            (Some(rank), Some(date_time)) => match options.dates.implausible_reason(date_time) {
+                Some(reason) => {
+                    // the implausible date-times of the sources before the one used explain why it was used
+                    if rank < chosen_rank.unwrap_or(usize::MAX) {
+                        implausible.push(format!("{} is implausible ({})", source, reason));
+                    }
+                    (CandidateStatus::Implausible, Some(reason))
+                }
+                None => (CandidateStatus::Outranked, None),
+            },
+        };
+
+        dates.push(DateCandidate {
+            source: source.name().to_string(),
+            rank: rank.map(|rank| rank + 1),
+            local: date_time.map(|date_time| date_time.local),
+            offset: date_time
+                .and_then(|date_time| date_time.offset.map(|offset| offset.to_string())),
+            utc: date_time.and_then(|date_time| date_time.utc()),
+            status,
+            note,
+            tags: raw_tags(file_path, planned.file_type, exif.as_ref(), *source),
+        });
+    }
+
+    Ok(Inspection {
+        file: file_path.to_path_buf(),
+        file_type: planned.file_type.to_string(),
+        sha256: planned.hash,
+        camera: planned.info.camera,
+        dates,
+        gps_offset: date_time_info.gps_offset().map(|offset| offset.to_string()),
+        clock_correction: planned.correction.map(|correction| correction.to_string()),
+        source: chosen.map(|source| source.name().to_string()),
+        reason: get_reason(chosen, &implausible, &options.dates),
+        destination: planned.path,
+        exif: exif.as_ref().map(exif_field_texts).unwrap_or_default(),
+    })
+}
+
+/// Explains why the source was used, including the implausible date-times of the sources before it.
+#[doc(hidden)]
+fn get_reason(chosen: Option<DateSource>, implausible: &[String], options: &DateOptions) -> String {
+    let skipped = if implausible.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", implausible.join(", "))
+    };
+
+    match chosen {
+        Some(DateSource::FileTimes) if !options.precedence.contains(&DateSource::FileTimes) => {
+            format!(
+                "No source in the precedence has a plausible date-time{}. The photo falls back to its file times.",
+                skipped
+            )
+        }
+        Some(source) => format!(
+            "{} is the first source in the precedence with a plausible date-time{}.",
+            source, skipped
+        ),
+        None => format!(
+            "No source has a plausible date-time{}. The photo is placed in the undated directory.",
+            skipped
+        ),
+    }
+}
+
+/// The raw values of the tags (or the files, properties, and atoms) a source reads its date-time from.
+#[doc(hidden)]
+fn raw_tags(
+    file_path: &Path,
+    file_type: FileType,
+    exif: Option<&exif::Exif>,
+    source: DateSource,
+) -> Vec<RawTag> {
+    let values = match source {
+        DateSource::Xmp => read_xmp_raw_values(file_path, exif),
+        DateSource::XmpSidecar => read_xmp_sidecar_raw_values(file_path),
+        DateSource::Takeout => read_takeout_raw_values(file_path),
+        DateSource::Video if file_type.is_video => read_video_raw_values(file_path),
+        DateSource::Video => Ok(Vec::new()),
+        _ => return raw_exif_tags(file_path, exif, source),
+    };
+
+    // a value that cannot be read is shown instead of the values
+    match values {
+        Ok(values) => values
+            .into_iter()
+            .map(|(name, value)| RawTag { name, value })
+            .collect(),
+        Err(err) => vec![RawTag {
+            name: "Error".to_string(),
+            value: format!("{:#}", err),
+        }],
+    }
+}
+
+/// The raw values of the EXIF tags a source reads its date-time from, or the file name.
+#[doc(hidden)]
+fn raw_exif_tags(file_path: &Path, exif: Option<&exif::Exif>, source: DateSource) -> Vec<RawTag> {
+    let tags: &[Tag] = match source {
+        DateSource::ExifOriginal => &[
+            Tag::DateTimeOriginal,
+            Tag::SubSecTimeOriginal,
+            Tag::OffsetTimeOriginal,
+        ],
+        DateSource::ExifDigitized => &[
+            Tag::DateTimeDigitized,
+            Tag::SubSecTimeDigitized,
+            Tag::OffsetTimeDigitized,
+        ],
+        DateSource::ExifBase => &[Tag::DateTime, Tag::SubSecTime, Tag::OffsetTime],
+        DateSource::Gps => &[Tag::GPSDateStamp, Tag::GPSTimeStamp],
+        DateSource::FileName => {
+            return vec![RawTag {
+                name: "FileName".to_string(),
+                value: file_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            }]
+        }
+        _ => &[],
+    };
+
+    let exif = match exif {
+        Some(exif) => exif,
+        None => return Vec::new(),
+    };
+
+    tags.iter()
+        .filter_map(|tag| {
+            exif.get_field(*tag, In::PRIMARY).map(|field| RawTag {
+                name: tag.to_string(),
+                value: raw_value_text(exif, field),
+            })
+        })
+        .collect()
+}
+
+/// ASCII values are shown as they are stored (e.g. `2021:06:01 23:30:00`), and other values as they are displayed.
+#[doc(hidden)]
+fn raw_value_text(exif: &exif::Exif, field: &exif::Field) -> String {
+    match &field.value {
+        exif::Value::Ascii(lines) => lines
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => field.display_value().with_unit(exif).to_string(),
+    }
+}
+
+/// Every EXIF field, with its tag, IFD, and value (with its unit).
+#[doc(hidden)]
+fn exif_field_texts(exif: &exif::Exif) -> Vec<ExifFieldText> {
+    exif.fields()
+        .map(|field| ExifFieldText {
+            tag: field.tag.to_string(),
+            ifd: field.ifd_num.to_string(),
+            value: field.display_value().with_unit(exif).to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// The name of the journal file kept in the organized directory.
+pub const JOURNAL_FILE_NAME: &str = "photiso-journal.jsonl";
+
+/// The kind of change recorded in the journal.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JournalKind {
+    /// A photo was moved to its organized location.
+    PhotoMoved,
+    /// A duplicate photo was moved to its duplicates location.
+    DuplicatePhotoMoved,
+    /// A photo was copied to its organized location.
+    PhotoCopied,
+    /// A sidecar file was moved with its photo.
+    SidecarMoved,
+    /// A sidecar file was copied with its photo.
+    SidecarCopied,
+    /// A file was moved back by undo.
+    Undone,
+    /// A copy was removed by undo because the original is still in place.
+    CopyRemoved,
+}
+
+/// A single change recorded in the journal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    /// The run that made the change.
+    pub run_id: String,
+    /// When the change was made.
+    pub timestamp: DateTime<Utc>,
+    /// The kind of change.
+    pub kind: JournalKind,
+    /// Where the file was.
+    pub from: PathBuf,
+    /// Where the file is now.
+    pub to: PathBuf,
+    /// The SHA256 hash of the file.
+    pub hash: String,
+}
+
+/// An append-only record of every file moved by a run.
+///
+/// The journal is a JSON lines file where each line is a `JournalEntry`.
+/// Each entry is flushed to disk as soon as it is appended so that the record survives an interrupted run.
+pub struct Journal {
+    run_id: String,
+    file: File,
+}
+
+impl Journal {
+    /// Opens the journal in the organized directory for appending the changes of a run.
+    pub fn open(organized_dir: &Path, run_id: &str) -> anyhow::Result<Journal> {
+        let path = get_journal_path(organized_dir);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Unable to open the journal {:?}.", path))?;
+
+        Ok(Journal {
+            run_id: run_id.to_string(),
+            file,
+        })
+    }
+
+    /// The id of the run whose changes are being appended.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Appends a change to the journal.
+    pub fn append(
+        &mut self,
+        kind: JournalKind,
+        from: &Path,
+        to: &Path,
+        hash: &str,
+    ) -> anyhow::Result<()> {
+        self.append_all(&[(kind, from, to, hash)])
+    }
+
+    /// Appends several changes to the journal in one write, so that either all of them are recorded or none are.
+    pub fn append_all(
+        &mut self,
+        changes: &[(JournalKind, &Path, &Path, &str)],
+    ) -> anyhow::Result<()> {
+        let mut lines = String::new();
+        for (kind, from, to, hash) in changes {
+            let entry = JournalEntry {
+                run_id: self.run_id.clone(),
+                timestamp: Utc::now(),
+                kind: *kind,
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                hash: hash.to_string(),
+            };
+
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+        }
+
+        // a write that fails part way is cut off, since a partial line would make the journal unreadable
+        let len = self.file.metadata()?.len();
+        if let Err(err) = self
+            .file
+            .write_all(lines.as_bytes())
+            .and_then(|_| self.file.sync_data())
+        {
+            let _ = self.file.set_len(len);
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates a new run id from the current date-time (i.e. YYYYMMDD-HHMMSS-FFF).
+pub fn new_run_id() -> String {
+    Utc::now().format("%Y%m%d-%H%M%S-%3f").to_string()
+}
+
+/// Gets the path of the journal in the organized directory.
+pub fn get_journal_path(organized_dir: &Path) -> PathBuf {
+    organized_dir.join(JOURNAL_FILE_NAME)
+}
+
+/// Reads every entry in the journal of the organized directory.
+pub fn read_journal(organized_dir: &Path) -> anyhow::Result<Vec<JournalEntry>> {
+    let path = get_journal_path(organized_dir);
+    let file =
+        File::open(&path).with_context(|| format!("Unable to open the journal {:?}.", path))?;
+
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "Unable to read line {} of the journal {:?}.",
+                index + 1,
+                path
+            )
+        })?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
@@ -1,30 +1,249 @@
+mod args;
+mod bmff;
+mod clock_correction;
 mod config;
 mod file_hash;
+mod file_move;
+mod file_name_date_time;
+mod file_type;
+mod inspect;
+mod journal;
+mod path_template;
 mod photo_date_time;
+mod photo_info;
 mod photo_organizer;
+mod raw_exif;
+mod takeout;
+mod undo;
+mod video_date_time;
+mod xmp_date_time;
 
+use crate::args::*;
+use crate::clock_correction::ClockCorrection;
 use crate::config::*;
+use crate::inspect::*;
+use crate::photo_date_time::{DateSource, NamingTimeZone};
 use crate::photo_organizer::*;
+use crate::undo::*;
+use structopt::StructOpt;
 
 fn main() -> anyhow::Result<()> {
-    let config: Config = load_config()?;
+    let args = Args::from_args();
+    let config: Config = load_config(&args)?;
 
-    print_header(&config);
+    match &args.command {
+        Some(Command::Undo { run_id }) => run_undo(&config, run_id),
+        Some(Command::Inspect { files, json }) => run_inspect(&config, files, *json),
+        None => run_organize(&config),
+    }
+}
+
+fn run_organize(config: &Config) -> anyhow::Result<()> {
+    print_header(config);
 
     let on_event = create_on_photiso_event(config.clone());
 
+    let options = create_organize_options(config);
+
     let result = photo_organizer::organize(
         &config.directories.unorganized,
         &config.directories.organized,
         &config.directories.duplicates,
+        &config.directories.undated,
+        &options,
         on_event,
     )?;
 
-    print_footer(&config, &result);
+    print_footer(config, &result);
 
     Ok(())
 }
 
+fn run_undo(config: &Config, run_id: &str) -> anyhow::Result<()> {
+    let output = config.options.output.clone();
+    let stop_on_error = config.options.stop_on_error;
+
+    let result = undo::undo(&config.directories.organized, run_id, move |event| {
+        on_undo_event(&output, &event);
+        !(stop_on_error && matches!(event, UndoEvent::Failed { .. }))
+    })?;
+
+    if config.options.output != "none" {
+        println!();
+        println!("========================================");
+        println!();
+        println!("Undo of run: {}", run_id);
+        println!("Files: {}", result.files);
+        println!("Restored: {}", result.files_restored);
+        println!("Missing: {}", result.files_missing);
+        println!("Changed: {}", result.files_changed);
+        println!("Occupied: {}", result.files_occupied);
+        println!("Copies removed: {}", result.copies_removed);
+        println!("Copies kept: {}", result.copies_kept);
+        println!("Errors: {}", result.files_errored);
+        println!();
+        println!("Duration: {:?}", result.duration);
+        println!("Run: {}", result.undo_run_id);
+        println!();
+        println!("========================================");
+    }
+
+    Ok(())
+}
+
+fn run_inspect(config: &Config, files: &[std::path::PathBuf], json: bool) -> anyhow::Result<()> {
+    let options = create_organize_options(config);
+
+    // a file that cannot be inspected does not stop the others from being inspected
+    let results: Vec<InspectionResult> = files
+        .iter()
+        .map(|file| {
+            inspect_result(
+                file,
+                &config.directories.unorganized,
+                &config.directories.organized,
+                &config.directories.undated,
+                &options,
+            )
+        })
+        .collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for result in &results {
+            match result {
+                InspectionResult::Inspected(inspection) => print_inspection(inspection),
+                InspectionResult::Failed(failure) => print_inspection_failure(failure),
+            }
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| matches!(result, InspectionResult::Failed(_)))
+        .count();
+    ensure!(
+        failed == 0,
+        "{} of {} files could not be inspected.",
+        failed,
+        results.len()
+    );
+
+    Ok(())
+}
+
+fn create_organize_options(config: &Config) -> OrganizeOptions {
+    OrganizeOptions {
+        mode: match config.options.mode.as_str() {
+            "copy" => OrganizeMode::Copy,
+            _ => OrganizeMode::Move,
+        },
+        dry_run: config.options.dry_run,
+        jobs: config.options.jobs,
+        organized_template: config.templates.organized.clone(),
+        duplicates_template: config.templates.duplicates.clone(),
+        time_zone: match config.options.time_zone.as_str() {
+            "utc" => NamingTimeZone::Utc,
+            _ => NamingTimeZone::Local,
+        },
+        clock_corrections: config.clock_corrections.clone(),
+        sidecar_extensions: config.options.sidecar_extensions.clone(),
+        dates: config.dates.clone(),
+        fix_extensions: config.options.fix_extensions,
+    }
+}
+
+fn print_inspection_failure(failure: &InspectionFailure) {
+    println!("========================================");
+    println!("{:?}", failure.file);
+    println!("========================================");
+    println!();
+    println!("error: {}", failure.error);
+    println!();
+}
+
+fn print_inspection(inspection: &Inspection) {
+    println!("========================================");
+    println!("{:?}", inspection.file);
+    println!("========================================");
+    println!();
+    println!("type: {}", inspection.file_type);
+    println!("sha256: {}", inspection.sha256);
+    let camera = &inspection.camera;
+    for (name, value) in [
+        ("make", &camera.make),
+        ("model", &camera.model),
+        ("lens", &camera.lens),
+        ("serial number", &camera.serial_number),
+    ]
+    .iter()
+    {
+        if let Some(value) = value {
+            println!("{}: {}", name, value);
+        }
+    }
+    println!();
+    println!("dates:");
+    for date in &inspection.dates {
+        let rank = match date.rank {
+            Some(rank) => format!("{}.", rank),
+            None => "-".to_string(),
+        };
+        let status = match (date.status, &date.note) {
+            (CandidateStatus::Chosen, _) => "chosen".to_string(),
+            (CandidateStatus::Outranked, _) => "not used".to_string(),
+            (CandidateStatus::Missing, _) => "missing".to_string(),
+            (CandidateStatus::Implausible, Some(note)) => format!("implausible: {}", note),
+            (CandidateStatus::Implausible, None) => "implausible".to_string(),
+            (CandidateStatus::NotTried, Some(note)) => note.clone(),
+            (CandidateStatus::NotTried, None) => "not tried".to_string(),
+        };
+        match date.local {
+            Some(local) => println!(
+                "  {:>3} {}: {}{}{} [{}]",
+                rank,
+                date.source,
+                local.format("%Y-%m-%d %H:%M:%S%.f"),
+                match &date.offset {
+                    Some(offset) => format!(" {}", offset),
+                    None => " (no offset)".to_string(),
+                },
+                match &date.utc {
+                    Some(utc) => format!(" = {} UTC", utc.format("%Y-%m-%d %H:%M:%S%.f")),
+                    None => String::new(),
+                },
+                status
+            ),
+            None => println!("  {:>3} {}: [{}]", rank, date.source, status),
+        }
+        for tag in &date.tags {
+            println!("        {}: {}", tag.name, tag.value);
+        }
+    }
+    if let Some(gps_offset) = &inspection.gps_offset {
+        println!("gps offset: {}", gps_offset);
+    }
+    if let Some(correction) = &inspection.clock_correction {
+        println!("clock correction: {}", correction);
+    }
+    println!();
+    println!(
+        "date source: {}",
+        inspection.source.as_deref().unwrap_or("none")
+    );
+    println!("reason: {}", inspection.reason);
+    println!("destination: {:?}", inspection.destination);
+    if !inspection.exif.is_empty() {
+        println!();
+        println!("exif:");
+        for field in &inspection.exif {
+            println!("  {} ({}): {}", field.tag, field.ifd, field.value);
+        }
+    }
+    println!();
+}
+
 fn create_on_photiso_event(config: Config) -> Box<dyn Fn(OrganizeEvent) -> bool> {
     Box::new(move |event| -> bool {
         on_photiso_event(&config, &event);
@@ -38,18 +257,62 @@ fn print_header(config: &Config) {
         println!("Photiso");
         println!("========================================");                
         println!();
+        if let Some(path) = &config.path {
+            println!("config: {:?}", path);
+        }
         println!("unorganized: {:?}", config.directories.unorganized);
         println!("organized: {:?}", config.directories.organized);
         println!("duplicates: {:?}", config.directories.duplicates);
+        println!("undated: {:?}", config.directories.undated);
+        println!();
+        println!("organized template: {}", config.templates.organized);
+        println!("duplicates template: {}", config.templates.duplicates);
         println!();
+        println!("mode: {}", config.options.mode);
+        println!("time zone: {}", config.options.time_zone);
+        println!("jobs: {}", jobs_text(config.options.jobs));
         println!("stop on error: {:?}", config.options.stop_on_error);
+        println!("dry run: {:?}", config.options.dry_run);
+        println!("fix extensions: {:?}", config.options.fix_extensions);
+        println!(
+            "sidecar extensions: {}",
+            config.options.sidecar_extensions.join(", ")
+        );
+        for correction in &config.clock_corrections {
+            println!("clock correction: {}", correction);
+        }
+        println!(
+            "date precedence: {}",
+            config
+                .dates
+                .precedence
+                .iter()
+                .map(|source| source.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        println!(
+            "fallback to file times: {:?}",
+            config.dates.fallback_to_file_times
+        );
+        for pattern in &config.dates.file_name_patterns {
+            println!("file name pattern: {}", pattern);
+        }
         println!();
+        if config.options.dry_run {
+            println!("DRY RUN: No files will be moved or copied. Each change is reported as it would happen.");
+            println!();
+        }
         if config.options.output == "compact" {
             println!("Progress Legend");
             println!("======");
             println!(". => a photo was moved to the organized directory.");
+            println!("+ => a photo was copied to the organized directory.");
             println!("_ => no change (photo is already in the correct location).");
             println!("* => a duplicate photo was moved to the duplicates directory.");
+            println!("# => a photo without a date was moved or copied to the undated directory.");
+            println!("~ => a sidecar file was moved or copied with its photo.");
+            println!("? => a sidecar file was found without its photo.");
             println!("^ => a file was skipped.");
             println!("! => there was a problem processing a file.");
             println!();
@@ -73,12 +336,25 @@ fn print_footer(config: &Config, result: &OrganizeResult) {
         println!();
         println!("Files: {}", result.files);
         println!("Moved: {}", result.photos_moved);
+        println!("Copied: {}", result.photos_copied);
         println!("Duplicates: {}", result.duplicate_photos_moved);
+        println!("Undated: {}", result.photos_undated);
+        println!("Sidecars moved: {}", result.sidecars_moved);
+        println!("Sidecars copied: {}", result.sidecars_copied);
+        println!("Sidecars without a photo: {}", result.sidecars_orphaned);
         println!("Skipped: {}", result.files_skipped);
         println!("Already correct: {}", result.photos_noop);
         println!("Errors: {}", result.files_errored);
+        println!("Verification failures: {}", result.files_unverified);
+        if config.options.dry_run {
+            println!();
+            println!("DRY RUN: No files were moved or copied.");
+        }
         println!();
         println!("Duration: {:?}", result.duration);
+        if let Some(run_id) = &result.run_id {
+            println!("Run: {} (undo with: photiso undo {})", run_id, run_id);
+        }
 
         let duration = result.duration.as_secs_f64();
         if duration > 0.0 {
@@ -100,7 +376,7 @@ fn on_photiso_event(config: &Config, event: &OrganizeEvent) -> bool {
         _ => on_photiso_event_default(event),
     }
 
-    if let OrganizeEvent::FileError { file: _, error: _ } = event {
+    if let OrganizeEvent::FileError { .. } | OrganizeEvent::FileVerificationFailed { .. } = event {
         if config.options.stop_on_error {
             return false;
         }
@@ -116,19 +392,31 @@ fn on_photiso_event_none(event: &OrganizeEvent) {
 
 fn on_photiso_event_compact(event: &OrganizeEvent) {
     match event {
-        OrganizeEvent::PhotoMoved { from: _, to: _ } => {
+        OrganizeEvent::PhotoMoved { .. } => {
             eprint!(".");
         }
-        OrganizeEvent::DuplicatePhotoMoved { from: _, to: _ } => {
+        OrganizeEvent::PhotoCopied { .. } => {
+            eprint!("+");
+        }
+        OrganizeEvent::DuplicatePhotoMoved { .. } => {
             eprint!("*");
         }
+        OrganizeEvent::PhotoUndated { .. } => {
+            eprint!("#");
+        }
+        OrganizeEvent::SidecarMoved { .. } | OrganizeEvent::SidecarCopied { .. } => {
+            eprint!("~");
+        }
+        OrganizeEvent::SidecarOrphaned { .. } => {
+            eprint!("?");
+        }
         OrganizeEvent::PhotoNoOp { file: _ } => {
             eprint!("_");
         }
         OrganizeEvent::FileSkipped { file: _, reason: _ } => {
             eprint!("^");
         }
-        OrganizeEvent::FileError { file: _, error: _ } => {
+        OrganizeEvent::FileError { .. } | OrganizeEvent::FileVerificationFailed { .. } => {
             eprint!("!");
         }
 
@@ -144,11 +432,92 @@ fn on_photiso_event_default(event: &OrganizeEvent) {
         OrganizeEvent::DirFinished { dir: _ } => {
             println!();
         }
-        OrganizeEvent::PhotoMoved { from, to } => {
-            println!("  Photo moved: {:?} -> {:?}", from, to);
+        OrganizeEvent::PhotoMoved {
+            from,
+            to,
+            simulated,
+            date_source,
+            correction,
+        } => {
+            println!(
+                "  Photo {}: {:?} -> {:?}{}{}",
+                moved_text(*simulated),
+                from,
+                to,
+                date_source_text(*date_source),
+                correction_text(*correction)
+            );
+        }
+        OrganizeEvent::PhotoCopied {
+            from,
+            to,
+            simulated,
+            date_source,
+            correction,
+        } => {
+            println!(
+                "  Photo {}: {:?} -> {:?}{}{}",
+                copied_text(*simulated),
+                from,
+                to,
+                date_source_text(*date_source),
+                correction_text(*correction)
+            );
+        }
+        OrganizeEvent::DuplicatePhotoMoved {
+            from,
+            to,
+            simulated,
+            date_source,
+            correction,
+        } => {
+            println!(
+                "  Duplicate photo {}: {:?} -> {:?}{}{}",
+                moved_text(*simulated),
+                from,
+                to,
+                date_source_text(*date_source),
+                correction_text(*correction)
+            );
         }
-        OrganizeEvent::DuplicatePhotoMoved { from, to } => {
-            println!("  Duplicate photo moved: {:?} -> {:?}", from, to);
+        OrganizeEvent::SidecarMoved {
+            from,
+            to,
+            simulated,
+        } => {
+            println!(
+                "  Sidecar {}: {:?} -> {:?}",
+                moved_text(*simulated),
+                from,
+                to
+            );
+        }
+        OrganizeEvent::SidecarCopied {
+            from,
+            to,
+            simulated,
+        } => {
+            println!(
+                "  Sidecar {}: {:?} -> {:?}",
+                copied_text(*simulated),
+                from,
+                to
+            );
+        }
+        OrganizeEvent::PhotoUndated {
+            from,
+            to,
+            simulated,
+        } => {
+            println!(
+                "  Undated photo {}: {:?} -> {:?}",
+                placed_text(*simulated),
+                from,
+                to
+            );
+        }
+        OrganizeEvent::SidecarOrphaned { file } => {
+            println!("  Sidecar without a photo (left in place): {:?}", file);
         }
         OrganizeEvent::PhotoNoOp { file } => {
             println!("  Already correct: {:?}", file);
@@ -159,7 +528,100 @@ fn on_photiso_event_default(event: &OrganizeEvent) {
         OrganizeEvent::FileError { file, error } => {
             println!("  File error: {:?} -> {:?}", file, error);
         }
+        OrganizeEvent::FileVerificationFailed {
+            file,
+            copy,
+            expected_hash,
+            actual_hash,
+        } => {
+            println!(
+                "  File verification failed (original left in place): {:?} -> {:?} expected hash {} but found {}",
+                file, copy, expected_hash, actual_hash
+            );
+        }
 
         _ => {}
     }
 }
+
+fn moved_text(simulated: bool) -> &'static str {
+    if simulated {
+        "would move"
+    } else {
+        "moved"
+    }
+}
+
+fn on_undo_event(output: &str, event: &UndoEvent) {
+    if output == "none" || output == "summary" {
+        return;
+    }
+
+    match event {
+        UndoEvent::Restored { from, to } => {
+            println!("  File restored: {:?} -> {:?}", from, to);
+        }
+        UndoEvent::Missing { file } => {
+            println!("  File missing (moved or removed since the run): {:?}", file);
+        }
+        UndoEvent::Changed { file } => {
+            println!("  File changed since the run (left in place): {:?}", file);
+        }
+        UndoEvent::Occupied { from, to } => {
+            println!(
+                "  Original location is occupied (left in place): {:?} -> {:?}",
+                from, to
+            );
+        }
+        UndoEvent::CopyRemoved { file, original } => {
+            println!("  Copy removed: {:?} (original: {:?})", file, original);
+        }
+        UndoEvent::CopyKept { file, original } => {
+            println!(
+                "  Copy kept because the original is no longer in place: {:?} (original: {:?})",
+                file, original
+            );
+        }
+        UndoEvent::Failed { file, error } => {
+            println!("  File error: {:?} -> {:?}", file, error);
+        }
+    }
+}
+
+fn copied_text(simulated: bool) -> &'static str {
+    if simulated {
+        "would copy"
+    } else {
+        "copied"
+    }
+}
+
+fn placed_text(simulated: bool) -> &'static str {
+    if simulated {
+        "would be placed"
+    } else {
+        "placed"
+    }
+}
+
+fn jobs_text(jobs: usize) -> String {
+    if jobs == 0 {
+        String::from("one per CPU")
+    } else {
+        jobs.to_string()
+    }
+}
+
+fn date_source_text(date_source: Option<DateSource>) -> String {
+    match date_source {
+        Some(date_source) => format!(" (date from {})", date_source),
+        None => String::new(),
+    }
+}
+
+fn correction_text(correction: Option<&ClockCorrection>) -> String {
+    match correction {
+        Some(correction) => format!(" (clock corrected by {})", correction),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,501 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use std::{fmt, path::PathBuf};
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// The template used for organized photos when one is not configured.
+/// i.e. YYYY/MM/YYYY-MM-DD HH-MM-SS-FFFFFFFFF CCC.ext
+pub const DEFAULT_ORGANIZED_TEMPLATE: &str =
+    "{year}/{month}/{year}-{month}-{day} {hour}-{minute}-{second}-{nanos}{conflict}.{ext}";
+
+/// The template used for duplicate photos when one is not configured.
+/// i.e. YYYY/MM/hash.CCC.ext
+pub const DEFAULT_DUPLICATES_TEMPLATE: &str = "{year}/{month}/{hash}{conflict}.{ext}";
+
+/// The tokens that can be used in a template.
+pub const TEMPLATE_TOKENS: &[&str] = &[
+    "year", "month", "day", "hour", "minute", "second", "nanos", "quarter", "stem", "make",
+    "model", "lens", "hash", "hash:N", "matched", "conflict", "ext",
+];
+
+/// The kind of path a template is for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemplateKind {
+    /// The path of a photo in the organized directory.
+    Organized,
+    /// The path of a duplicate photo in the duplicates directory.
+    Duplicates,
+}
+
+impl TemplateKind {
+    /// The template used when one is not configured.
+    pub fn default_template(self) -> &'static str {
+        match self {
+            TemplateKind::Organized => DEFAULT_ORGANIZED_TEMPLATE,
+            TemplateKind::Duplicates => DEFAULT_DUPLICATES_TEMPLATE,
+        }
+    }
+}
+
+impl fmt::Display for TemplateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateKind::Organized => f.write_str("organized"),
+            TemplateKind::Duplicates => f.write_str("duplicates"),
+        }
+    }
+}
+
+/// The values used to render a template for a photo.
+pub struct TemplateValues<'a> {
+    /// The date-time the photo was taken.
+    pub date_time: NaiveDateTime,
+    /// The original file name without its extension.
+    pub stem: &'a str,
+    /// The original extension, made lowercase for consistency.
+    pub ext: &'a str,
+    /// The camera manufacturer.
+    pub make: Option<&'a str>,
+    /// The camera model.
+    pub model: Option<&'a str>,
+    /// The lens model.
+    pub lens: Option<&'a str>,
+    /// The SHA256 hash of the photo.
+    pub hash: &'a str,
+    /// The file name, without its extension, of the organized photo that a duplicate matched.
+    pub matched: Option<&'a str>,
+    /// The conflict number; 0 when there is no conflict.
+    pub conflict: u32,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq)]
+enum TemplateToken {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Nanos,
+    Quarter,
+    Stem,
+    Make,
+    Model,
+    Lens,
+    Hash(Option<usize>),
+    Matched,
+    Conflict,
+    Ext,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Token(TemplateToken),
+    Separator,
+}
+
+/// A template for the path of a photo, relative to the directory it is placed in.
+///
+/// Tokens are written in braces (e.g. `{year}/{month}/{stem}{conflict}.{ext}`) and `/` separates directories.
+/// Templates are validated when they are parsed so that an invalid template fails before any file is moved.
+#[derive(Clone)]
+pub struct PathTemplate {
+    kind: TemplateKind,
+    text: String,
+    parts: Vec<TemplatePart>,
+}
+
+impl PathTemplate {
+    /// Parses and validates a template of the given kind.
+    pub fn parse(text: &str, kind: TemplateKind) -> anyhow::Result<PathTemplate> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') => {
+                                bail!("The template '{}' has a '{{' inside a token.", text)
+                            }
+                            Some(c) => name.push(c),
+                            None => bail!("The template '{}' has a '{{' without a '}}'.", text),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Text(literal.clone()));
+                        literal.clear();
+                    }
+                    parts.push(TemplatePart::Token(parse_token(text, &name)?));
+                }
+                '}' => bail!("The template '{}' has a '}}' without a '{{'.", text),
+                '/' | '\\' => {
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Text(literal.clone()));
+                        literal.clear();
+                    }
+                    parts.push(TemplatePart::Separator);
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Text(literal));
+        }
+
+        let template = PathTemplate {
+            kind,
+            text: text.to_string(),
+            parts,
+        };
+        template.validate()?;
+
+        Ok(template)
+    }
+
+    /// The template used for the kind when one is not configured.
+    pub fn default_for(kind: TemplateKind) -> PathTemplate {
+        PathTemplate::parse(kind.default_template(), kind).unwrap()
+    }
+
+    /// Renders the template into a relative path.
+    pub fn render(&self, values: &TemplateValues) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => component.push_str(text),
+                TemplatePart::Token(token) => {
+                    component.push_str(&render_token(token, self.kind, values))
+                }
+                TemplatePart::Separator => {
+                    path.push(&component);
+                    component.clear();
+                }
+            }
+        }
+        path.push(&component);
+
+        path
+    }
+
+    #[doc(hidden)]
+    fn validate(&self) -> anyhow::Result<()> {
+        // sidecars and companions are named by replacing the extension at the end of the file name, and a conflict
+        // must make the file name unique rather than its directory
+        let file_name = self.file_name_parts();
+        ensure!(
+            file_name.contains(&TemplatePart::Token(TemplateToken::Conflict)),
+            "The template '{}' must contain {{conflict}} in its file name so that photos taken at the same time are not overwritten.",
+            self.text
+        );
+        ensure!(
+            matches!(
+                file_name,
+                [.., TemplatePart::Text(text), TemplatePart::Token(TemplateToken::Ext)] if text.ends_with('.')
+            ),
+            "The template '{}' must end with .{{ext}} so that the extension of the photo is kept.",
+            self.text
+        );
+        ensure!(
+            !matches!(self.parts.first(), Some(TemplatePart::Separator)),
+            "The template '{}' must be relative; it cannot start with '/'.",
+            self.text
+        );
+        ensure!(
+            self.kind == TemplateKind::Duplicates || !self.has_token(&TemplateToken::Matched),
+            "The template '{}' cannot contain {{matched}}; it can only be used in the duplicates template.",
+            self.text
+        );
+
+        // every directory and the file name must be something other than '.' or '..'
+        let mut component = String::new();
+        let mut has_tokens = false;
+        for part in self
+            .parts
+            .iter()
+            .chain(std::iter::once(&TemplatePart::Separator))
+        {
+            match part {
+                TemplatePart::Text(text) => component.push_str(text),
+                TemplatePart::Token(_) => has_tokens = true,
+                TemplatePart::Separator => {
+                    let text = component.trim();
+                    ensure!(
+                        has_tokens || !(text.is_empty() || text == "." || text == ".."),
+                        "The template '{}' has an empty, '.', or '..' directory.",
+                        self.text
+                    );
+                    component.clear();
+                    has_tokens = false;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The parts of the file name, after the last separator.
+    #[doc(hidden)]
+    fn file_name_parts(&self) -> &[TemplatePart] {
+        let start = self
+            .parts
+            .iter()
+            .rposition(|part| *part == TemplatePart::Separator)
+            .map_or(0, |separator| separator + 1);
+        &self.parts[start..]
+    }
+
+    #[doc(hidden)]
+    fn has_token(&self, token: &TemplateToken) -> bool {
+        self.parts.iter().any(|part| match part {
+            TemplatePart::Token(t) => t == token,
+            _ => false,
+        })
+    }
+}
+
+impl fmt::Debug for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{:?}", self.text))
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[doc(hidden)]
+fn parse_token(text: &str, name: &str) -> anyhow::Result<TemplateToken> {
+    let token = match name {
+        "year" => TemplateToken::Year,
+        "month" => TemplateToken::Month,
+        "day" => TemplateToken::Day,
+        "hour" => TemplateToken::Hour,
+        "minute" => TemplateToken::Minute,
+        "second" => TemplateToken::Second,
+        "nanos" => TemplateToken::Nanos,
+        "quarter" => TemplateToken::Quarter,
+        "stem" => TemplateToken::Stem,
+        "make" => TemplateToken::Make,
+        "model" => TemplateToken::Model,
+        "lens" => TemplateToken::Lens,
+        "hash" => TemplateToken::Hash(None),
+        "matched" => TemplateToken::Matched,
+        "conflict" => TemplateToken::Conflict,
+        "ext" => TemplateToken::Ext,
+        _ => match name
+            .strip_prefix("hash:")
+            .map(|length| length.parse::<usize>())
+        {
+            Some(Ok(length)) if length > 0 => TemplateToken::Hash(Some(length)),
+            _ => bail!(
+                "The template '{}' has an unknown token '{{{}}}'. Use one of: {}.",
+                text,
+                name,
+                TEMPLATE_TOKENS
+                    .iter()
+                    .map(|t| format!("{{{}}}", t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        },
+    };
+
+    Ok(token)
+}
+
+#[doc(hidden)]
+fn render_token(token: &TemplateToken, kind: TemplateKind, values: &TemplateValues) -> String {
+    let date_time = &values.date_time;
+    match token {
+        TemplateToken::Year => date_time.format("%Y").to_string(),
+        TemplateToken::Month => date_time.format("%m").to_string(),
+        TemplateToken::Day => date_time.format("%d").to_string(),
+        TemplateToken::Hour => date_time.format("%H").to_string(),
+        TemplateToken::Minute => date_time.format("%M").to_string(),
+        TemplateToken::Second => date_time.format("%S").to_string(),
+        TemplateToken::Nanos => format!("{:09}", date_time.nanosecond() % 1_000_000_000),
+        TemplateToken::Quarter => format!("Q{}", (date_time.month() - 1) / 3 + 1),
+        TemplateToken::Stem => sanitize(values.stem),
+        TemplateToken::Make => sanitize(values.make.unwrap_or("")),
+        TemplateToken::Model => sanitize(values.model.unwrap_or("")),
+        TemplateToken::Lens => sanitize(values.lens.unwrap_or("")),
+        TemplateToken::Hash(None) => values.hash.to_string(),
+        TemplateToken::Hash(Some(length)) => values.hash.chars().take(*length).collect(),
+        TemplateToken::Matched => sanitize(values.matched.unwrap_or("")),
+        // conflict is ' CCC' for organized photos and '.CCC' for duplicates (i.e. a 3-digit number),
+        // or nothing when there is no conflict
+        TemplateToken::Conflict => match (values.conflict, kind) {
+            (0, _) => String::new(),
+            (conflict, TemplateKind::Organized) => format!(" {:03}", conflict),
+            (conflict, TemplateKind::Duplicates) => format!(".{:03}", conflict),
+        },
+        TemplateToken::Ext => values.ext.to_string(),
+    }
+}
+
+/// Makes a value safe to use in a file or directory name.
+#[doc(hidden)]
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    match value.trim_end_matches('.') {
+        "" => String::from("Unknown"),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn values(conflict: u32) -> TemplateValues<'static> {
+        TemplateValues {
+            date_time: NaiveDate::from_ymd_opt(2021, 6, 1)
+                .unwrap()
+                .and_hms_nano_opt(23, 30, 0, 5)
+                .unwrap(),
+            stem: "IMG_0001",
+            ext: "jpg",
+            make: Some("Canon"),
+            model: None,
+            lens: None,
+            hash: "ABCDEF0123",
+            matched: Some("2021-06-01 23-30-00-000000005"),
+            conflict,
+        }
+    }
+
+    fn render(text: &str, kind: TemplateKind, conflict: u32) -> PathBuf {
+        PathTemplate::parse(text, kind)
+            .unwrap()
+            .render(&values(conflict))
+    }
+
+    #[test]
+    fn default_templates_render() {
+        assert_eq!(
+            PathTemplate::default_for(TemplateKind::Organized).render(&values(0)),
+            PathBuf::from("2021/06/2021-06-01 23-30-00-000000005.jpg")
+        );
+        assert_eq!(
+            PathTemplate::default_for(TemplateKind::Duplicates).render(&values(2)),
+            PathBuf::from("2021/06/ABCDEF0123.002.jpg")
+        );
+    }
+
+    #[test]
+    fn tokens_render() {
+        assert_eq!(
+            render(
+                "{make}/{model}/{quarter}/{stem}-{hash:4}{conflict}.{ext}",
+                TemplateKind::Organized,
+                1
+            ),
+            PathBuf::from("Canon/Unknown/Q2/IMG_0001-ABCD 001.jpg")
+        );
+        assert_eq!(
+            render("{matched}{conflict}.{ext}", TemplateKind::Duplicates, 0),
+            PathBuf::from("2021-06-01 23-30-00-000000005.jpg")
+        );
+    }
+
+    #[test]
+    fn valid_templates_parse() {
+        for text in [
+            "{year}/{stem}{conflict}.{ext}",
+            "{year}\\{month}\\{stem}{conflict}.{ext}",
+            "{ext}/{year}/{stem}{conflict}.{ext}",
+            "{year}/{conflict}{stem}.{ext}",
+        ]
+        .iter()
+        {
+            assert!(
+                PathTemplate::parse(text, TemplateKind::Organized).is_ok(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn file_name_must_end_with_ext() {
+        for text in [
+            "{year}/{stem}.{ext}{conflict}",
+            "{year}/{stem}{conflict}{ext}",
+            "{year}/{stem}{conflict}.{ext}.bak",
+            "{ext}/{year}/{stem}{conflict}",
+            "{year}/{stem}{conflict}",
+        ]
+        .iter()
+        {
+            assert!(
+                PathTemplate::parse(text, TemplateKind::Organized).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn file_name_must_contain_conflict() {
+        for text in [
+            "{conflict}/{year}/{stem}.{ext}",
+            "{year}{conflict}/{stem}.{ext}",
+            "{year}/{stem}.{ext}",
+        ]
+        .iter()
+        {
+            assert!(
+                PathTemplate::parse(text, TemplateKind::Organized).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_templates_do_not_parse() {
+        for text in [
+            "/{year}/{stem}{conflict}.{ext}",
+            "{year}//{stem}{conflict}.{ext}",
+            "{year}/../{stem}{conflict}.{ext}",
+            "{year/{stem}{conflict}.{ext}",
+            "{year}}/{stem}{conflict}.{ext}",
+            "{year}/{unknown}{conflict}.{ext}",
+            "{year}/{hash:0}{conflict}.{ext}",
+            "{year}/{matched}{conflict}.{ext}",
+        ]
+        .iter()
+        {
+            assert!(
+                PathTemplate::parse(text, TemplateKind::Organized).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+}
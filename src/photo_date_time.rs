@@ -1,6 +1,11 @@
-use chrono::TimeZone;
+use crate::file_name_date_time::*;
+use crate::file_type::*;
+use crate::takeout::*;
+use crate::video_date_time::*;
+use crate::xmp_date_time::*;
+use chrono::{Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
 use exif::{In, Tag};
-use std::{fs, fs::File, path::Path};
+use std::{fmt, fs, path::Path};
 
 #[doc(hidden)]
 pub use anyhow::*;
@@ -8,122 +13,481 @@ pub use anyhow::*;
 #[doc(hidden)]
 pub use chrono::{DateTime, Utc};
 
+/// The time zone of the date-times used in folder and file names.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NamingTimeZone {
+    /// The local time where the photo was taken (i.e. the time on the camera clock).
+    #[default]
+    Local,
+    /// UTC. When the offset of the photo is not known, its local time is used instead.
+    Utc,
+}
+
+/// A source of the date-time a photo was taken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateSource {
+    /// The EXIF `DateTimeOriginal` tag
+    ExifOriginal,
+    /// The EXIF `GPSDateStamp` and `GPSTimeStamp` tags
+    Gps,
+    /// The XMP packet embedded in the photo (e.g. `xmp:CreateDate`)
+    Xmp,
+    /// The `.xmp` sidecar file of the photo
+    XmpSidecar,
+    /// The Google Takeout JSON file
+    Takeout,
+    /// The EXIF `DateTimeDigitized` tag
+    ExifDigitized,
+    /// The EXIF `DateTime` tag
+    ExifBase,
+    /// The QuickTime or MP4 metadata of a video
+    Video,
+    /// The date-time in the file name
+    FileName,
+    /// The created and modified times of the file
+    FileTimes,
+}
+
+/// Every date source, in the order they are preferred by default.
+pub const DEFAULT_PRECEDENCE: &[DateSource] = &[
+    DateSource::ExifOriginal,
+    DateSource::Gps,
+    DateSource::Xmp,
+    DateSource::XmpSidecar,
+    DateSource::Takeout,
+    DateSource::ExifDigitized,
+    DateSource::ExifBase,
+    DateSource::Video,
+    DateSource::FileName,
+    DateSource::FileTimes,
+];
+
+/// The sources whose date-times are read from the camera clock, and are shifted by a clock correction.
+const CAMERA_CLOCK_SOURCES: &[DateSource] = &[
+    DateSource::ExifOriginal,
+    DateSource::ExifDigitized,
+    DateSource::ExifBase,
+    DateSource::Video,
+];
+
+impl DateSource {
+    /// The name of the source, as written in the configuration file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateSource::ExifOriginal => "exifOriginal",
+            DateSource::Gps => "gps",
+            DateSource::Xmp => "xmp",
+            DateSource::XmpSidecar => "xmpSidecar",
+            DateSource::Takeout => "takeout",
+            DateSource::ExifDigitized => "exifDigitized",
+            DateSource::ExifBase => "exifBase",
+            DateSource::Video => "video",
+            DateSource::FileName => "fileName",
+            DateSource::FileTimes => "fileTimes",
+        }
+    }
+
+    /// Finds a source by its name, as written in the configuration file.
+    pub fn parse(text: &str) -> anyhow::Result<DateSource> {
+        match DEFAULT_PRECEDENCE
+            .iter()
+            .find(|source| source.name() == text.trim())
+        {
+            Some(source) => Ok(*source),
+            None => bail!(
+                "The date source '{}' is not valid. Use one of: {}.",
+                text,
+                DEFAULT_PRECEDENCE
+                    .iter()
+                    .map(|source| source.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Options that change how the date-time a photo was taken is found.
+#[derive(Clone, Debug)]
+pub struct DateOptions {
+    /// The patterns of the dates in file names, tried in order.
+    pub file_name_patterns: Vec<FileNamePattern>,
+    /// The sources of the date-time, in the order they are preferred.
+    pub precedence: Vec<DateSource>,
+    /// The earliest plausible date-time a photo was taken.
+    pub earliest: NaiveDateTime,
+    /// The latest plausible date-time a photo was taken, or None for a day after now.
+    pub latest: Option<NaiveDateTime>,
+    /// The date-times that cameras and computers fall back to when their clock was never set (e.g. after the clock
+    /// battery died), which are never plausible.
+    pub bogus_date_times: Vec<NaiveDateTime>,
+    /// Whether the file times are used when a photo has no other date-time. After a photo is copied, its file times are
+    /// usually the date it was copied rather than the date it was taken.
+    pub fallback_to_file_times: bool,
+}
+
+impl Default for DateOptions {
+    fn default() -> Self {
+        DateOptions {
+            file_name_patterns: FileNamePattern::built_in(),
+            precedence: DEFAULT_PRECEDENCE.to_vec(),
+            earliest: NaiveDate::from_ymd(1900, 1, 1).and_hms(0, 0, 0),
+            latest: None,
+            bogus_date_times: default_bogus_date_times(),
+            fallback_to_file_times: true,
+        }
+    }
+}
+
+impl DateOptions {
+    /// Determines if a photo could have been taken at the date-time: it is within the earliest and latest date-times,
+    /// and is not a bogus date-time in either its local time or UTC. Fractions of a second are ignored when comparing
+    /// to the bogus date-times.
+    pub fn is_plausible(&self, date_time: &PhotoDateTime) -> bool {
+        self.implausible_reason(date_time).is_none()
+    }
+
+    /// The sources that are tried for a photo, in order: the precedence, followed by the file times when it falls
+    /// back to them.
+    pub fn sources(&self) -> Vec<DateSource> {
+        let mut sources = self.precedence.clone();
+        if self.fallback_to_file_times && !sources.contains(&DateSource::FileTimes) {
+            sources.push(DateSource::FileTimes);
+        }
+
+        sources
+    }
+
+    /// Explains why a photo could not have been taken at the date-time, or None if it is plausible.
+    pub fn implausible_reason(&self, date_time: &PhotoDateTime) -> Option<String> {
+        let latest = self
+            .latest
+            .unwrap_or_else(|| Local::now().naive_local() + Duration::days(1));
+        if date_time.local < self.earliest {
+            return Some(format!("before the earliest date-time {}", self.earliest));
+        }
+        if date_time.local > latest {
+            return Some(format!("after the latest date-time {}", latest));
+        }
+
+        [NamingTimeZone::Local, NamingTimeZone::Utc]
+            .iter()
+            .filter_map(|time_zone| date_time.naive(*time_zone).with_nanosecond(0))
+            .find(|naive| self.bogus_date_times.contains(naive))
+            .map(|naive| format!("the bogus date-time {}", naive))
+    }
+}
+
+/// The Unix epoch, the DOS (i.e. FAT) epoch, and the date many cameras reset to.
+pub fn default_bogus_date_times() -> Vec<NaiveDateTime> {
+    [(1970, 1, 1), (1980, 1, 1), (2000, 1, 1)]
+        .iter()
+        .map(|(year, month, day)| NaiveDate::from_ymd(*year, *month, *day).and_hms(0, 0, 0))
+        .collect()
+}
+
+/// Cameras record the time of their last GPS fix, which can be a little before the photo was taken. The difference
+/// between the camera clock and the GPS time is only taken as the offset of the camera from UTC when it is within this
+/// many seconds of a whole quarter hour.
+const MAX_GPS_FIX_AGE_SECONDS: i64 = 2 * 60;
+
+/// The offsets of the time zones on Earth are within 14 hours of UTC.
+const MAX_OFFSET_SECONDS: i64 = 14 * 60 * 60;
+
+/// A date-time a photo may have been taken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhotoDateTime {
+    /// The local date-time where the photo was taken
+    pub local: NaiveDateTime,
+    /// The offset of the local date-time from UTC, if it is known
+    pub offset: Option<FixedOffset>,
+}
+
+impl PhotoDateTime {
+    /// Creates a date-time from a UTC date-time, in the local time zone of this computer.
+    pub fn from_naive_utc(utc: &NaiveDateTime) -> PhotoDateTime {
+        let date_time = Local.from_utc_datetime(utc);
+        PhotoDateTime {
+            local: date_time.naive_local(),
+            offset: Some(*date_time.offset()),
+        }
+    }
+
+    /// The date-time in UTC, if the offset is known.
+    pub fn utc(&self) -> Option<DateTime<Utc>> {
+        self.offset
+            .map(|offset| Utc.from_utc_datetime(&(self.local - offset)))
+    }
+
+    /// The date-time to use in names for the time zone.
+    pub fn naive(&self, time_zone: NamingTimeZone) -> NaiveDateTime {
+        match (time_zone, self.utc()) {
+            (NamingTimeZone::Utc, Some(utc)) => utc.naive_utc(),
+            _ => self.local,
+        }
+    }
+}
+
 /// Date-time information for a photo
 pub struct PhotoDateTimeInfo {
     /// When the file was created
-    created: chrono::DateTime<Utc>,
+    created: PhotoDateTime,
     /// When the file was last modified
-    modified: chrono::DateTime<Utc>,
+    modified: PhotoDateTime,
     /// When the photo was taken (least precise)
-    exif_base: Option<chrono::DateTime<Utc>>,
+    exif_base: Option<PhotoDateTime>,
     /// When the photo was originally taken (most precise)
-    exif_original: Option<chrono::DateTime<Utc>>,
+    exif_original: Option<PhotoDateTime>,
     /// When the photo was digitized to camera memory
-    exif_digitized: Option<chrono::DateTime<Utc>>,
+    exif_digitized: Option<PhotoDateTime>,
+    /// When the photo was taken, from the XMP packet embedded in it
+    xmp: Option<PhotoDateTime>,
+    /// When the photo was taken, from its `.xmp` sidecar file
+    xmp_sidecar: Option<PhotoDateTime>,
+    /// When the photo was taken, from its Google Takeout JSON file
+    takeout: Option<PhotoDateTime>,
+    /// When the video was recorded, from its QuickTime or MP4 metadata
+    video: Option<PhotoDateTime>,
+    /// When the photo was taken, from the date-time in its file name
+    file_name: Option<PhotoDateTime>,
+    /// When the photo was taken in UTC, from the GPS receiver (which is not affected by the camera clock)
+    gps: Option<NaiveDateTime>,
 }
 
 impl PhotoDateTimeInfo {
-    /// Finds the date-time that is best to use as the taken date-time.
-    /// This prefers exif original, digitized, and base (in order).
-    /// This falls back to the file's earliest created or modified date-time.
-    pub fn best(&self) -> chrono::DateTime<Utc> {
-        if let Some(exif_original) = self.exif_original {
-            return exif_original.clone();
-        }
+    /// Finds the date-time that is best to use as the taken date-time, and the source it came from: the first source
+    /// in the precedence that the photo has a plausible date-time for (by default exif original, GPS, XMP, XMP
+    /// sidecar, Google Takeout, digitized, base, video, file name, and file times).
+    /// This falls back to the file's earliest created or modified date-time when no source in the precedence has one,
+    /// unless the options do not fall back to file times.
+    /// Returns None when none of the date-times are plausible.
+    pub fn best(&self, options: &DateOptions) -> Option<(PhotoDateTime, DateSource)> {
+        options.sources().iter().find_map(|source| {
+            self.get(*source)
+                .filter(|date_time| options.is_plausible(date_time))
+                .map(|date_time| (date_time, *source))
+        })
+    }
 
-        if let Some(exif_digitized) = self.exif_digitized {
-            return exif_digitized.clone();
+    /// The date-time on the camera clock (the first EXIF or video date-time in the precedence), plausible or not.
+    /// Clock corrections are matched against it, since a camera whose clock was reset (e.g. to 2000-01-01) only has a
+    /// plausible date-time once it is corrected.
+    pub fn camera_date_time(&self, options: &DateOptions) -> Option<PhotoDateTime> {
+        options
+            .sources()
+            .iter()
+            .chain(CAMERA_CLOCK_SOURCES)
+            .filter(|source| CAMERA_CLOCK_SOURCES.contains(source))
+            .find_map(|source| self.get(*source))
+    }
+
+    /// The date-time from a source, if the photo has one.
+    /// EXIF date-times without an offset tag are given the offset derived from the GPS time, when there is one.
+    pub fn get(&self, source: DateSource) -> Option<PhotoDateTime> {
+        match source {
+            DateSource::ExifOriginal => self.with_gps_offset(self.exif_original),
+            DateSource::Gps => self.gps_date_time(),
+            DateSource::Xmp => self.xmp,
+            DateSource::XmpSidecar => self.xmp_sidecar,
+            DateSource::Takeout => self.takeout,
+            DateSource::ExifDigitized => self.with_gps_offset(self.exif_digitized),
+            DateSource::ExifBase => self.with_gps_offset(self.exif_base),
+            DateSource::Video => self.video,
+            DateSource::FileName => self.file_name,
+            DateSource::FileTimes => Some(self.file_times()),
         }
+    }
 
-        if let Some(exif_base) = self.exif_base {
-            return exif_base.clone();
+    /// The offset of the camera clock from UTC, derived from the difference between `DateTimeOriginal` and the GPS
+    /// time rounded to the nearest quarter hour. None when the photo does not have both, or the difference is not
+    /// close to a time zone (e.g. the camera clock was set wrong).
+    pub fn gps_offset(&self) -> Option<FixedOffset> {
+        let difference = (self.exif_original?.local - self.gps?).num_seconds();
+        let offset = (difference + 450).div_euclid(900) * 900;
+
+        if (difference - offset).abs() > MAX_GPS_FIX_AGE_SECONDS
+            || offset.abs() > MAX_OFFSET_SECONDS
+        {
+            return None;
         }
 
-        if self.modified < self.created {
-            return self.created.clone();
+        FixedOffset::east_opt(offset as i32)
+    }
+
+    /// The GPS time in the time zone of the camera when it is known (from `OffsetTimeOriginal` or the GPS offset);
+    /// otherwise in the local time zone of this computer.
+    #[doc(hidden)]
+    fn gps_date_time(&self) -> Option<PhotoDateTime> {
+        let gps = self.gps?;
+        let offset = self
+            .exif_original
+            .and_then(|original| original.offset)
+            .or_else(|| self.gps_offset());
+
+        Some(match offset {
+            Some(offset) => PhotoDateTime {
+                local: gps + offset,
+                offset: Some(offset),
+            },
+            None => PhotoDateTime::from_naive_utc(&gps),
+        })
+    }
+
+    #[doc(hidden)]
+    fn with_gps_offset(&self, date_time: Option<PhotoDateTime>) -> Option<PhotoDateTime> {
+        date_time.map(|date_time| PhotoDateTime {
+            offset: date_time.offset.or_else(|| self.gps_offset()),
+            ..date_time
+        })
+    }
+
+    /// A copied file can be modified before it was created, so the earliest of the two is used.
+    #[doc(hidden)]
+    fn file_times(&self) -> PhotoDateTime {
+        if self.created.local < self.modified.local {
+            return self.created;
         }
 
-        return self.modified.clone();
+        self.modified
     }
 
-    /// Loads the photo date-times for a file based on metadata and EXIF information.
-    pub fn load(file_path: &Path) -> anyhow::Result<PhotoDateTimeInfo> {
-        let file = File::open(&file_path)?;
+    /// Shifts the EXIF and video date-times to correct a camera clock that was set wrong.
+    /// The file times, XMP, Google Takeout, file name, and GPS date-times are not shifted. Returns false when there are no
+    /// EXIF or video date-times to shift.
+    pub fn correct_clock(&mut self, shift: chrono::Duration) -> bool {
+        let mut corrected = false;
+        for date_time in [
+            &mut self.exif_base,
+            &mut self.exif_original,
+            &mut self.exif_digitized,
+            &mut self.video,
+        ]
+        .iter_mut()
+        .filter_map(|date_time| date_time.as_mut())
+        {
+            date_time.local += shift;
+            corrected = true;
+        }
 
+        corrected
+    }
+
+    /// Loads the photo date-times for a file based on metadata, EXIF information, XMP packets, video metadata, its
+    /// XMP sidecar and Google Takeout JSON files, and its name.
+    /// The EXIF information is None when the file does not have any.
+    pub fn load(
+        file_path: &Path,
+        file_type: FileType,
+        exif: Option<&exif::Exif>,
+        options: &DateOptions,
+    ) -> anyhow::Result<PhotoDateTimeInfo> {
         let metadata = fs::metadata(file_path)?;
-        let created = convert_system_time_to_chrono_date_time(&metadata.created()?)?;
-        let modified = convert_system_time_to_chrono_date_time(&metadata.modified()?)?;
+        let created = convert_system_time_to_photo_date_time(metadata.created()?);
+        let modified = convert_system_time_to_photo_date_time(metadata.modified()?);
 
-        let mut exif_base: Option<DateTime<Utc>> = None;
-        let mut exif_original: Option<DateTime<Utc>> = None;
-        let mut exif_digitized: Option<DateTime<Utc>> = None;
+        let mut exif_base: Option<PhotoDateTime> = None;
+        let mut exif_original: Option<PhotoDateTime> = None;
+        let mut exif_digitized: Option<PhotoDateTime> = None;
+        let mut gps: Option<NaiveDateTime> = None;
 
-        let mut bufreader = std::io::BufReader::new(&file);
-        let exifreader = exif::Reader::new();
-        if let Ok(exif) = exifreader.read_from_container(&mut bufreader) {
+        if let Some(exif) = exif {
             if let Some(date_time) =
-                get_exif_chrono_date_time_pair(&exif, Tag::DateTime, Tag::SubSecTime)
+                get_exif_photo_date_time(exif, Tag::DateTime, Tag::SubSecTime, Tag::OffsetTime)
             {
                 exif_base = Some(date_time);
             }
 
-            if let Some(date_time) = get_exif_chrono_date_time_pair(
-                &exif,
+            if let Some(date_time) = get_exif_photo_date_time(
+                exif,
                 Tag::DateTimeOriginal,
                 Tag::SubSecTimeOriginal,
+                Tag::OffsetTimeOriginal,
             ) {
                 exif_original = Some(date_time);
             }
 
-            if let Some(date_time) = get_exif_chrono_date_time_pair(
-                &exif,
+            if let Some(date_time) = get_exif_photo_date_time(
+                exif,
                 Tag::DateTimeDigitized,
                 Tag::SubSecTimeDigitized,
+                Tag::OffsetTimeDigitized,
             ) {
                 exif_digitized = Some(date_time);
             }
+
+            gps = get_exif_gps_date_time(exif);
         }
 
+        // only videos have QuickTime or MP4 metadata, and a video that cannot be read is treated like a photo without
+        // EXIF information
+        let video = if file_type.is_video {
+            read_video_date_time(file_path).ok().flatten()
+        } else {
+            None
+        };
+
+        // XMP packets and sidecars that cannot be read are ignored
+        let xmp = read_xmp_date_time(file_path, exif).ok().flatten();
+        let xmp_sidecar = read_xmp_sidecar_date_time(file_path).ok().flatten();
+
+        // a Takeout JSON file that cannot be read is ignored
+        let takeout = read_takeout_date_time(file_path).ok().flatten();
+
+        let file_name = read_file_name_date_time(file_path, &options.file_name_patterns);
+
         return Ok(PhotoDateTimeInfo {
             created,
             modified,
             exif_base,
             exif_original,
             exif_digitized,
+            xmp,
+            xmp_sidecar,
+            takeout,
+            video,
+            file_name,
+            gps,
         });
     }
 }
 
-// -------------------- std::time::SystemTime -> chrono::DateTime conversion -------------------- //
+// -------------------- std::time::SystemTime -> PhotoDateTime conversion -------------------- //
 
+/// File times are converted to the local time zone of this computer.
 #[doc(hidden)]
-fn convert_system_time_to_chrono_date_time(
-    value: &std::time::SystemTime,
-) -> anyhow::Result<chrono::DateTime<Utc>> {
-    let created_duration = value.duration_since(std::time::SystemTime::UNIX_EPOCH)?;
-    Ok(Utc.timestamp(
-        created_duration.as_secs() as i64,
-        created_duration.subsec_nanos(),
-    ))
+fn convert_system_time_to_photo_date_time(value: std::time::SystemTime) -> PhotoDateTime {
+    PhotoDateTime::from_naive_utc(&DateTime::<Utc>::from(value).naive_utc())
 }
 
-// -------------------- EXIF -> chrono::DateTime conversion -------------------- //
+// -------------------- EXIF -> PhotoDateTime conversion -------------------- //
 
+/// None when the month is not valid (e.g. month 13 of a corrupt EXIF date).
 #[doc(hidden)]
-fn days_in_month(year: i32, month: u32) -> i64 {
-    if month == 12 {
-        chrono::NaiveDate::from_ymd(year + 1, 1, 1)
+fn days_in_month(year: i32, month: u32) -> Option<i64> {
+    let next_month = if month == 12 {
+        chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1)
     } else {
-        chrono::NaiveDate::from_ymd(year, month + 1, 1)
-    }
-    .signed_duration_since(chrono::NaiveDate::from_ymd(year, month, 1))
-    .num_days()
+        chrono::NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }?;
+
+    Some(
+        next_month
+            .signed_duration_since(chrono::NaiveDate::from_ymd_opt(year, month, 1)?)
+            .num_days(),
+    )
 }
 
 // Unfortunately, exif::DateTime can have components beyond the bounds of a valid date and time
 // (e.g. 400 minutes, 80 seconds, etc.)
+// Times are carried into the next day, but a month that does not exist cannot be, so the date-time is None
 #[doc(hidden)]
-fn ensure_valid_exif_date_time(exif_date_time: &exif::DateTime) -> exif::DateTime {
+fn ensure_valid_exif_date_time(exif_date_time: &exif::DateTime) -> Option<exif::DateTime> {
     let mut year = exif_date_time.year;
     let mut month = exif_date_time.month;
     let mut day = exif_date_time.day;
@@ -146,7 +510,7 @@ fn ensure_valid_exif_date_time(exif_date_time: &exif::DateTime) -> exif::DateTim
     day += hour / 24;
     hour = hour % 24;
 
-    let mut cur_days_in_month = days_in_month(year as i32, month as u32) as u8;
+    let mut cur_days_in_month = days_in_month(year as i32, month as u32)? as u8;
     while day > cur_days_in_month {
         day -= cur_days_in_month;
         month += 1;
@@ -156,13 +520,13 @@ fn ensure_valid_exif_date_time(exif_date_time: &exif::DateTime) -> exif::DateTim
             year += 1;
         }
 
-        cur_days_in_month = days_in_month(year as i32, month as u32) as u8;
+        cur_days_in_month = days_in_month(year as i32, month as u32)? as u8;
     }
 
     year += (month / 13) as u16;
     month = month % 13;
 
-    return exif::DateTime {
+    return Some(exif::DateTime {
         year,
         month,
         day,
@@ -171,7 +535,7 @@ fn ensure_valid_exif_date_time(exif_date_time: &exif::DateTime) -> exif::DateTim
         second,
         nanosecond,
         offset: exif_date_time.offset,
-    };
+    });
 }
 
 // Tests if the date/time are greater than 0
@@ -184,25 +548,29 @@ fn is_positive_exif_date_time(exif_date_time: &exif::DateTime) -> bool {
         && exif_date_time.day > 0
 }
 
+/// EXIF date-times are the local time of the camera. The offset from UTC is only known when an offset tag is present.
+/// None when the date-time is not valid, so it is treated like a missing date-time.
 #[doc(hidden)]
-fn convert_exif_to_chrono_date_time(exif_date_time: &exif::DateTime) -> chrono::DateTime<Utc> {
-    let date = Utc.ymd(
+fn convert_exif_to_photo_date_time(exif_date_time: &exif::DateTime) -> Option<PhotoDateTime> {
+    let date = chrono::NaiveDate::from_ymd_opt(
         exif_date_time.year as i32,
         exif_date_time.month as u32,
         exif_date_time.day as u32,
-    );
+    )?;
 
-    let date_time = date.and_hms_nano(
+    let local = date.and_hms_nano_opt(
         exif_date_time.hour as u32,
         exif_date_time.minute as u32,
         exif_date_time.second as u32,
         exif_date_time.nanosecond.unwrap_or(0),
-    );
+    )?;
 
-    match exif_date_time.offset {
-        Some(offset_minutes) => date_time + chrono::Duration::minutes(offset_minutes as i64),
-        None => date_time,
-    }
+    // the offset is in minutes east of UTC (i.e. local = UTC + offset)
+    let offset = exif_date_time
+        .offset
+        .and_then(|offset_minutes| FixedOffset::east_opt(offset_minutes as i32 * 60));
+
+    Some(PhotoDateTime { local, offset })
 }
 
 #[doc(hidden)]
@@ -211,8 +579,7 @@ fn convert_exif_value_to_date_time(value: &exif::Value) -> Option<exif::DateTime
         if lines.len() > 0 {
             if let Ok(date_time) = exif::DateTime::from_ascii(&lines[0]) {
                 if is_positive_exif_date_time(&date_time) {
-                    let date_time = ensure_valid_exif_date_time(&date_time);
-                    return Some(date_time);
+                    return ensure_valid_exif_date_time(&date_time);
                 }
             }
         }
@@ -221,14 +588,14 @@ fn convert_exif_value_to_date_time(value: &exif::Value) -> Option<exif::DateTime
     None
 }
 
+/// SubSecTime tags are the digits after the decimal point of the seconds (e.g. `5` is 500 milliseconds and `000123`
+/// is 123 microseconds), so their length matters.
 #[doc(hidden)]
-fn convert_exif_value_to_u32(value: &exif::Value) -> Option<u32> {
+fn convert_exif_value_to_sub_sec_nanos(value: &exif::Value) -> Option<u32> {
     if let exif::Value::Ascii(lines) = value {
-        if lines.len() > 0 {
-            if let Ok(line) = std::str::from_utf8(&lines[0]) {
-                if let Ok(number) = line.parse() {
-                    return Some(number);
-                }
+        if let Some(line) = lines.first() {
+            if let Ok(text) = std::str::from_utf8(line) {
+                return parse_sub_sec_nanos(text);
             }
         }
     }
@@ -236,62 +603,67 @@ fn convert_exif_value_to_u32(value: &exif::Value) -> Option<u32> {
     None
 }
 
+/// Parses the `SubSecTime` digits of a fraction of a second as nanoseconds. Cameras pad the digits with spaces.
 #[doc(hidden)]
-#[allow(dead_code)]
-fn convert_exif_value_to_string(value: &exif::Value) -> Option<String> {
-    if let exif::Value::Ascii(lines) = value {
-        if lines.len() > 0 {
-            if let Ok(text) = std::str::from_utf8(&lines[0]) {
-                return Some(text.to_string());
-            }
-        }
+fn parse_sub_sec_nanos(text: &str) -> Option<u32> {
+    fraction_nanos(text.trim_matches(|c: char| c.is_whitespace() || c == '\0'))
+}
+
+/// The digits of a fraction of a second (e.g. `123` is 123 milliseconds) as nanoseconds. Digits beyond nanoseconds
+/// are dropped.
+pub fn fraction_nanos(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
 
-    None
+    let digits = &digits[..digits.len().min(9)];
+    let value: u32 = digits.parse().ok()?;
+    Some(value * 10u32.pow(9 - digits.len() as u32))
 }
 
 // -------------------- EXIF helpers -------------------- //
 
 #[doc(hidden)]
-fn get_exif_chrono_date_time(exif: &exif::Exif, tag: Tag) -> Option<chrono::DateTime<Utc>> {
+fn get_exif_date_time(exif: &exif::Exif, tag: Tag, offset_tag: Tag) -> Option<PhotoDateTime> {
     if let Some(field) = exif.get_field(tag, In::PRIMARY) {
-        if let Some(exif_date_time) = convert_exif_value_to_date_time(&field.value) {
-            let chrono_date_time = convert_exif_to_chrono_date_time(&exif_date_time);
-            return Some(chrono_date_time);
-        }
-    }
-
-    None
-}
+        if let Some(mut exif_date_time) = convert_exif_value_to_date_time(&field.value) {
+            // the offset is optional (e.g. '+09:00'); a missing or blank offset leaves the time zone unknown
+            if let Some(exif::Value::Ascii(lines)) =
+                exif.get_field(offset_tag, In::PRIMARY).map(|f| &f.value)
+            {
+                if let Some(line) = lines.first() {
+                    if exif_date_time.parse_offset(line).is_err() {
+                        exif_date_time.offset = None;
+                    }
+                }
+            }
 
-#[doc(hidden)]
-fn get_exif_field_u32(exif: &exif::Exif, tag: Tag) -> Option<u32> {
-    if let Some(field) = exif.get_field(tag, In::PRIMARY) {
-        return convert_exif_value_to_u32(&field.value);
+            return convert_exif_to_photo_date_time(&exif_date_time);
+        }
     }
 
     None
 }
 
 #[doc(hidden)]
-#[allow(dead_code)]
-fn get_exif_field_string(exif: &exif::Exif, tag: Tag) -> Option<String> {
+fn get_exif_field_sub_sec_nanos(exif: &exif::Exif, tag: Tag) -> Option<u32> {
     if let Some(field) = exif.get_field(tag, In::PRIMARY) {
-        return convert_exif_value_to_string(&field.value);
+        return convert_exif_value_to_sub_sec_nanos(&field.value);
     }
 
     None
 }
 
 #[doc(hidden)]
-fn get_exif_chrono_date_time_pair(
+fn get_exif_photo_date_time(
     exif: &exif::Exif,
     date_tag: Tag,
     sub_sec_tag: Tag,
-) -> Option<chrono::DateTime<Utc>> {
-    if let Some(date_time) = get_exif_chrono_date_time(exif, date_tag) {
-        if let Some(sub_sec) = get_exif_field_u32(exif, sub_sec_tag) {
-            return Some(date_time + chrono::Duration::milliseconds(sub_sec as i64));
+    offset_tag: Tag,
+) -> Option<PhotoDateTime> {
+    if let Some(mut date_time) = get_exif_date_time(exif, date_tag, offset_tag) {
+        if let Some(nanos) = get_exif_field_sub_sec_nanos(exif, sub_sec_tag) {
+            date_time.local += chrono::Duration::nanoseconds(nanos as i64);
         }
 
         return Some(date_time);
@@ -300,21 +672,131 @@ fn get_exif_chrono_date_time_pair(
     None
 }
 
+/// The GPS date is `YYYY:MM:DD` and the GPS time is the hour, minute, and second (which may have a fraction) in UTC.
+/// Receivers without a fix leave the date blank.
 #[doc(hidden)]
-#[allow(dead_code)]
-fn print_all_exif(file_path: &Path) -> anyhow::Result<()> {
-    let file = File::open(&file_path)?;
-    let mut buf_reader = std::io::BufReader::new(&file);
-    let exif_reader = exif::Reader::new();
-    let exif = exif_reader.read_from_container(&mut buf_reader)?;
-    for f in exif.fields() {
-        println!(
-            "{} {} {}",
-            f.tag,
-            f.ifd_num,
-            f.display_value().with_unit(&exif)
+fn get_exif_gps_date_time(exif: &exif::Exif) -> Option<NaiveDateTime> {
+    let date = match &exif.get_field(Tag::GPSDateStamp, In::PRIMARY)?.value {
+        exif::Value::Ascii(lines) => {
+            let text = std::str::from_utf8(lines.first()?).ok()?;
+            NaiveDate::parse_from_str(text.trim(), "%Y:%m:%d").ok()?
+        }
+        _ => return None,
+    };
+
+    let seconds: f64 = match &exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value {
+        exif::Value::Rational(parts) if parts.len() == 3 => parts
+            .iter()
+            .zip([3600.0, 60.0, 1.0].iter())
+            .map(|(part, unit)| part.to_f64() * unit)
+            .sum(),
+        _ => return None,
+    };
+
+    // a zero denominator makes the time infinite or not a number
+    if !(0.0..86400.0).contains(&seconds) {
+        return None;
+    }
+
+    Some(date.and_hms(0, 0, 0) + Duration::nanoseconds((seconds * 1e9).round() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub_sec_digits_are_a_decimal_fraction() {
+        let cases = [
+            ("5", 500_000_000),
+            ("12", 120_000_000),
+            ("123", 123_000_000),
+            ("1234", 123_400_000),
+            ("12345", 123_450_000),
+            ("123456", 123_456_000),
+            ("1234567", 123_456_700),
+            ("12345678", 123_456_780),
+            ("123456789", 123_456_789),
+        ];
+
+        for (text, nanos) in cases.iter() {
+            assert_eq!(
+                parse_sub_sec_nanos(text),
+                Some(*nanos),
+                "SubSecTime '{}'",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn sub_sec_leading_zeros_are_kept() {
+        let cases = [
+            ("0", 0),
+            ("05", 50_000_000),
+            ("005", 5_000_000),
+            ("000123", 123_000),
+            ("000000001", 1),
+        ];
+
+        for (text, nanos) in cases.iter() {
+            assert_eq!(
+                parse_sub_sec_nanos(text),
+                Some(*nanos),
+                "SubSecTime '{}'",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn sub_sec_padding_is_ignored_and_extra_digits_are_dropped() {
+        assert_eq!(parse_sub_sec_nanos("50  "), Some(500_000_000));
+        assert_eq!(parse_sub_sec_nanos(" 7\0"), Some(700_000_000));
+        assert_eq!(parse_sub_sec_nanos("1234567891"), Some(123_456_789));
+    }
+
+    #[test]
+    fn sub_sec_without_digits_is_none() {
+        for text in ["", "   ", "abc", "-5", "1.5"].iter() {
+            assert_eq!(parse_sub_sec_nanos(text), None, "SubSecTime '{}'", text);
+        }
+    }
+
+    #[test]
+    fn sub_sec_exif_value_is_converted() {
+        let value = exif::Value::Ascii(vec![b"047".to_vec()]);
+        assert_eq!(
+            convert_exif_value_to_sub_sec_nanos(&value),
+            Some(47_000_000)
         );
+
+        let value = exif::Value::Short(vec![47]);
+        assert_eq!(convert_exif_value_to_sub_sec_nanos(&value), None);
+    }
+
+    fn exif_date_time(text: &str) -> Option<PhotoDateTime> {
+        let value = exif::Value::Ascii(vec![text.as_bytes().to_vec()]);
+        convert_exif_value_to_date_time(&value)
+            .and_then(|date_time| convert_exif_to_photo_date_time(&date_time))
     }
 
-    Ok(())
+    #[test]
+    fn exif_date_time_out_of_range_times_are_carried() {
+        let date_time = exif_date_time("2020:12:31 23:59:60").unwrap();
+        assert_eq!(
+            date_time.local,
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn exif_date_time_with_invalid_month_is_none() {
+        assert!(exif_date_time("2020:13:01 00:00:00").is_none());
+        assert!(exif_date_time("2020:99:01 00:00:00").is_none());
+        assert!(exif_date_time("0000:00:00 00:00:00").is_none());
+    }
 }
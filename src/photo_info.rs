@@ -0,0 +1,103 @@
+use crate::file_type::*;
+use crate::photo_date_time::*;
+use crate::raw_exif::*;
+use exif::{In, Tag};
+use serde::Serialize;
+use std::{fs::File, path::Path};
+
+/// The camera that took a photo
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraInfo {
+    /// The camera manufacturer
+    pub make: Option<String>,
+    /// The camera model
+    pub model: Option<String>,
+    /// The lens model
+    pub lens: Option<String>,
+    /// The camera body serial number
+    pub serial_number: Option<String>,
+}
+
+/// Information about a photo, read from its metadata and EXIF information
+pub struct PhotoInfo {
+    /// The date-times the photo may have been taken
+    pub date_time_info: PhotoDateTimeInfo,
+    /// The camera that took the photo
+    pub camera: CameraInfo,
+}
+
+impl PhotoInfo {
+    /// Loads the information for a photo. The EXIF information is only read once.
+    pub fn load(
+        file_path: &Path,
+        file_type: FileType,
+        options: &DateOptions,
+    ) -> anyhow::Result<PhotoInfo> {
+        let exif = read_exif(file_path)?;
+
+        let date_time_info = PhotoDateTimeInfo::load(file_path, file_type, exif.as_ref(), options)?;
+
+        let camera = match &exif {
+            Some(exif) => CameraInfo {
+                make: get_exif_field_string(exif, Tag::Make),
+                model: get_exif_field_string(exif, Tag::Model),
+                lens: get_exif_field_string(exif, Tag::LensModel),
+                serial_number: get_exif_field_string(exif, Tag::BodySerialNumber),
+            },
+            None => CameraInfo {
+                make: None,
+                model: None,
+                lens: None,
+                serial_number: None,
+            },
+        };
+
+        Ok(PhotoInfo {
+            date_time_info,
+            camera,
+        })
+    }
+}
+
+/// Reads the EXIF information of a file, or None if the file does not have any.
+pub fn read_exif(file_path: &Path) -> anyhow::Result<Option<exif::Exif>> {
+    let file = File::open(file_path)?;
+
+    let mut bufreader = std::io::BufReader::new(&file);
+    let exifreader = exif::Reader::new();
+
+    if let Ok(exif) = exifreader.read_from_container(&mut bufreader) {
+        return Ok(Some(exif));
+    }
+
+    // camera RAW formats that are not plain TIFF files
+    Ok(read_raw_exif(file_path).ok().flatten())
+}
+
+// -------------------- EXIF helpers -------------------- //
+
+#[doc(hidden)]
+fn convert_exif_value_to_string(value: &exif::Value) -> Option<String> {
+    if let exif::Value::Ascii(lines) = value {
+        if let Some(line) = lines.first() {
+            if let Ok(text) = std::str::from_utf8(line) {
+                let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+                if !text.is_empty() {
+                    return Some(text.to_string());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[doc(hidden)]
+fn get_exif_field_string(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    if let Some(field) = exif.get_field(tag, In::PRIMARY) {
+        return convert_exif_value_to_string(&field.value);
+    }
+
+    None
+}
@@ -1,11 +1,22 @@
+use crate::clock_correction::*;
 use crate::file_hash::*;
+use crate::file_move::*;
+use crate::file_type::*;
+use crate::journal::*;
+use crate::path_template::*;
 use crate::photo_date_time::*;
+use crate::photo_info::*;
+use crate::takeout::*;
+use chrono::NaiveDateTime;
+use rayon::prelude::*;
 use std::{
-    cell::Cell,
-    ffi::OsString,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    env,
     fs, io,
     path::Path,
     path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
@@ -31,24 +42,155 @@ pub enum OrganizeEvent<'a> {
         file: &'a Path,
         error: anyhow::Error,
     },
+    /// Raised when a copy of a file does not match the original. The original is left in place.
+    FileVerificationFailed {
+        file: &'a Path,
+        copy: &'a Path,
+        expected_hash: &'a str,
+        actual_hash: &'a str,
+    },
     /// Raised when photo is moved to its organized location.
-    PhotoMoved { from: &'a Path, to: &'a Path },
+    /// When `simulated` is true, this is a dry run and the photo was not actually moved.
+    /// `date_source` is where the date-time of the photo (or of its companion, when it has none) came from.
+    /// `correction` is the clock correction applied to the date-time of the photo, if any.
+    PhotoMoved {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+        date_source: Option<DateSource>,
+        correction: Option<&'a ClockCorrection>,
+    },
     /// Raised when duplicate photo is moved to its duplicates location.
-    DuplicatePhotoMoved { from: &'a Path, to: &'a Path },
+    /// When `simulated` is true, this is a dry run and the photo was not actually moved.
+    /// `date_source` is where the date-time of the photo (or of its companion, when it has none) came from.
+    /// `correction` is the clock correction applied to the date-time of the photo, if any.
+    DuplicatePhotoMoved {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+        date_source: Option<DateSource>,
+        correction: Option<&'a ClockCorrection>,
+    },
+    /// Raised when photo is copied to its organized location.
+    /// When `simulated` is true, this is a dry run and the photo was not actually copied.
+    /// `date_source` is where the date-time of the photo (or of its companion, when it has none) came from.
+    /// `correction` is the clock correction applied to the date-time of the photo, if any.
+    PhotoCopied {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+        date_source: Option<DateSource>,
+        correction: Option<&'a ClockCorrection>,
+    },
+    /// Raised when a photo without a plausible date-time (including a photo with only file times, when they are not
+    /// used) is moved (or copied) to the undated directory, to be dated by hand.
+    /// When `simulated` is true, this is a dry run and the photo was not actually moved.
+    PhotoUndated {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+    },
+    /// Raised when a sidecar file is moved with its photo, to its organized or duplicates location.
+    /// When `simulated` is true, this is a dry run and the sidecar was not actually moved.
+    SidecarMoved {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+    },
+    /// Raised when a sidecar file is copied with its photo to its organized location.
+    /// When `simulated` is true, this is a dry run and the sidecar was not actually copied.
+    SidecarCopied {
+        from: &'a Path,
+        to: &'a Path,
+        simulated: bool,
+    },
+    /// Raised when a sidecar file does not have a photo with the same name. It is left in place.
+    SidecarOrphaned { file: &'a Path },
     /// Raised when photo is already at its organized location.
     PhotoNoOp { file: &'a Path },
 }
 
+/// The name of the directory for undated photos, in the organized directory (when no other is given) and in the
+/// duplicates directory.
+pub const UNDATED_DIR_NAME: &str = "undated";
+
+/// The extensions of the sidecar files that follow their photo when none are configured.
+pub const DEFAULT_SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "json"];
+
+/// How photos are placed in the organized directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OrganizeMode {
+    /// Photos are moved, leaving the unorganized directory empty of photos.
+    #[default]
+    Move,
+    /// Photos are copied, leaving the unorganized directory untouched.
+    Copy,
+}
+
+/// Options that change how photos are organized.
+#[derive(Clone, Debug)]
+pub struct OrganizeOptions {
+    /// Whether photos are moved or copied.
+    pub mode: OrganizeMode,
+    /// Plan every move without touching the disk.
+    pub dry_run: bool,
+    /// The number of files read and hashed in parallel, or 0 for one per CPU.
+    pub jobs: usize,
+    /// The template for the path of an organized photo, relative to the organized directory.
+    pub organized_template: PathTemplate,
+    /// The template for the path of a duplicate photo, relative to the duplicates directory.
+    pub duplicates_template: PathTemplate,
+    /// The time zone of the date-times used in the paths.
+    pub time_zone: NamingTimeZone,
+    /// The corrections for cameras whose clocks were set wrong. The first that matches a photo is applied.
+    pub clock_corrections: Vec<ClockCorrection>,
+    /// The extensions (lower case, without the `.`) of the sidecar files that follow their photo.
+    pub sidecar_extensions: Vec<String>,
+    /// How the date-time a photo was taken is found.
+    pub dates: DateOptions,
+    /// Give photos whose extension does not match their contents (e.g. a PNG named `.jpg`) the usual extension of
+    /// their format.
+    pub fix_extensions: bool,
+}
+
+impl Default for OrganizeOptions {
+    fn default() -> Self {
+        OrganizeOptions {
+            mode: OrganizeMode::default(),
+            dry_run: false,
+            jobs: 0,
+            organized_template: PathTemplate::default_for(TemplateKind::Organized),
+            duplicates_template: PathTemplate::default_for(TemplateKind::Duplicates),
+            time_zone: NamingTimeZone::default(),
+            clock_corrections: Vec::new(),
+            sidecar_extensions: DEFAULT_SIDECAR_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            dates: DateOptions::default(),
+            fix_extensions: false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct OrganizeResult {
+    /// The id of the run in the journal, or None for a dry run.
+    pub run_id: Option<String>,
     pub dirs: u64,
     pub dirs_skipped: u64,
     pub files: u64,
     pub files_skipped: u64,
     pub files_errored: u64,
+    pub files_unverified: u64,
     pub photos_moved: u64,
+    pub photos_copied: u64,
     pub duplicate_photos_moved: u64,
     pub photos_noop: u64,
+    pub photos_undated: u64,
+    pub sidecars_moved: u64,
+    pub sidecars_copied: u64,
+    pub sidecars_orphaned: u64,
     pub duration: Duration,
 }
 
@@ -59,17 +201,65 @@ pub struct OrganizeResult {
 /// * `unorganized_dir` - The directory containing the photos that need to be organized.
 /// * `organized_dir` - The directory where organized photos should be placed.
 /// * `duplicates_dir` - The directory where exact duplicate photos should be placed.
+/// * `undated_dir` - The directory where photos without a plausible date-time should be placed.
+/// * `options` - The options that change how photos are organized.
 /// * `event_handler` - The handler for listening to events as organize progreses.
 ///
 /// To organize photos _in place_, pass the same directory for `unorganized_dir` and `organized_dir`.
 ///
-/// The `duplicates_dir` cannot be the same directory as `unorganized_dir` nor `organized_dir`.
+/// The `duplicates_dir` cannot be the same directory as `unorganized_dir` nor `organized_dir`, and the `undated_dir`
+/// cannot be the same directory as any of the others. The `undated_dir` is usually within the `organized_dir`; it is
+/// not organized again.
 ///
 /// If the `event_handler` returns true, organize continues; otherwise organize will stop processing files and return.
 ///
+/// The date-time and hash of the files in each directory are read in parallel by `options.jobs` workers.
+/// The destinations are then allocated one file at a time in file name order, so conflict numbers are the same no
+/// matter how many jobs are used.
+///
+/// If `options.mode` is `Copy`, photos are copied instead of moved and each copy is verified against the original.
+/// A photo that has already been copied to its organized location is not copied again, nor treated as a duplicate.
+///
+/// When a photo cannot be renamed to a different file system, it is copied, verified, and only then removed from
+/// the unorganized directory. A copy that does not match its original raises `FileVerificationFailed` and the
+/// original is left in place.
+///
+/// Every file that is moved or copied is appended to the journal in the `organized_dir` so that the run can be undone.
+///
+/// If `options.dry_run` is true, no directories are created and no files are moved. Instead, each move is planned
+/// against a simulated view of the destination (which includes the moves planned earlier in the same run) and
+/// raised as a simulated event.
+///
 /// # Organization Details
 ///
-/// Only files with an extension of bmp, gif, jpg, jpeg, png, tif, tiff, or wmp are processed. Others are skipped.
+/// Photos are placed at `options.organized_template` relative to the `organized_dir`.
+/// By default this is `YYYY/MM/YYYY-MM-DD HH-MM-SS-FFFFFFFFF.ext` with ` CCC` added before the extension when
+/// there is a conflict.
+///
+/// Duplicates are placed at `options.duplicates_template` relative to the `duplicates_dir`.
+/// By default this is `YYYY/MM/hash.ext` with `.CCC` added before the extension when there is a conflict.
+///
+/// A photo is dated by the first plausible date-time of `options.dates` (see `PhotoDateTimeInfo::best`). A photo
+/// without one is placed in the `undated_dir` at its path relative to the `unorganized_dir`, keeping its name, with
+/// ` CCC` added before the extension when there is a conflict. An undated duplicate is placed at the same path in an
+/// `undated` directory of the `duplicates_dir`. Companions without a date-time of their own are dated by the primary
+/// photo of their group (or the first companion with one).
+///
+/// Only photos and videos are processed, which are recognized by their contents rather than their extension (see
+/// `detect_file_type`): bmp, gif, jpeg, png, tiff, jpeg xr, heif, avif, and webp photos, camera RAW files (cr2, cr3,
+/// nef, arw, dng, raf, orf, and rw2), and mov, mp4, and 3gp videos. Others are skipped. A photo keeps its extension
+/// (in lower case), unless it has none or `options.fix_extensions` is set and its extension does not match its
+/// contents, in which case it is given the usual extension of its format.
+///
+/// Photos in the same directory with the same name but a different extension (e.g. a RAW+JPEG pair, or the photo and
+/// video of a Live Photo) are companions. They are organized together: each is named after the first that is not a
+/// video, keeping its own extension, and they share a conflict number. If one of them cannot be moved, the others are
+/// put back and skipped.
+///
+/// Files with an extension in `options.sidecar_extensions` (by default xmp, aae, and json) are sidecars. A sidecar
+/// named after a photo (e.g. `IMG_0001.xmp`) or after its whole file name (e.g. `IMG_0001.jpg.json`) is moved (or
+/// copied) with the photo to its organized or duplicates location and renamed to match it. A sidecar without a photo
+/// raises `SidecarOrphaned` and is left in place.
 ///
 /// If a filename contains an exclamation point `!`, it will be skipped.
 ///
@@ -77,34 +267,106 @@ pub fn organize<F>(
     unorganized_dir: &Path,
     organized_dir: &Path,
     duplicates_dir: &Path,
+    undated_dir: &Path,
+    options: &OrganizeOptions,
     event_handler: F,
 ) -> anyhow::Result<OrganizeResult>
 where
     F: Fn(OrganizeEvent) -> bool,
 {
     // each of the directories must exist, otherwise canonicalize will fail.
-    if !unorganized_dir.exists() {
-        fs::create_dir_all(&unorganized_dir)?;
-    }
+    // a dry run must not touch the disk, so missing directories are simulated instead.
+    if !options.dry_run {
+        if !unorganized_dir.exists() {
+            fs::create_dir_all(unorganized_dir)?;
+        }
 
-    if !organized_dir.exists() {
-        fs::create_dir_all(&organized_dir)?;
-    }
+        if !organized_dir.exists() {
+            fs::create_dir_all(organized_dir)?;
+        }
 
-    if !&duplicates_dir.exists() {
-        fs::create_dir_all(&duplicates_dir)?;
+        if !duplicates_dir.exists() {
+            fs::create_dir_all(duplicates_dir)?;
+        }
     }
 
     let organizer = Organizer::new(
         unorganized_dir,
         organized_dir,
         duplicates_dir,
+        undated_dir,
+        options.clone(),
         event_handler,
     )?;
 
     Ok(organizer.organize()?)
 }
 
+/// Where a photo would be organized, and what was used to work it out.
+pub struct PlannedPhoto {
+    /// The path the photo would be placed at, before any conflict with an existing file.
+    pub path: PathBuf,
+    /// The clock correction applied to the date-time, if any.
+    pub correction: Option<ClockCorrection>,
+    /// The format of the photo, recognized by its contents.
+    pub file_type: FileType,
+    /// The SHA256 hash of the photo.
+    pub hash: String,
+    /// The information of the photo, with its camera clock corrected.
+    pub info: PhotoInfo,
+    /// The source of the date-time used for the path, or None when the photo is undated.
+    pub source: Option<DateSource>,
+}
+
+/// Works out where a photo would be organized on its own, without touching the disk: its organized path, or its path
+/// in the undated directory when it does not have a plausible date-time. Companions, duplicates, and conflicts with
+/// existing files are not considered. A photo outside the unorganized directory is placed by its name alone.
+pub fn plan_photo(
+    file_path: &Path,
+    unorganized_dir: &Path,
+    organized_dir: &Path,
+    undated_dir: &Path,
+    options: &OrganizeOptions,
+) -> anyhow::Result<PlannedPhoto> {
+    let file_type = match detect_file_type(file_path)? {
+        Some(file_type) => file_type,
+        None => bail!(
+            "The file '{}' is not a known photo or video format.",
+            file_path.display()
+        ),
+    };
+    let (photo_info, correction) = load_corrected_photo_info(file_path, file_type, options)?;
+    let photo = prepare_loaded_photo(file_path, file_type, &photo_info, correction, options)?;
+
+    let path = match photo.date {
+        Some(_) => get_organized_photo_path(
+            file_path,
+            &photo.extension,
+            &photo,
+            0,
+            organized_dir,
+            &options.organized_template,
+        )?,
+        None => {
+            let file_path = fs::canonicalize(file_path)?;
+            let unorganized_dir = match canonicalize_dir(unorganized_dir)? {
+                dir if file_path.starts_with(&dir) => dir,
+                _ => file_path.parent().unwrap().to_path_buf(),
+            };
+            get_undated_photo_path(&file_path, &photo, 0, &unorganized_dir, undated_dir)
+        }
+    };
+
+    Ok(PlannedPhoto {
+        path,
+        correction: photo.correction,
+        file_type,
+        hash: photo.hash,
+        info: photo_info,
+        source: photo.date.map(|(_, source)| source),
+    })
+}
+
 struct OrganizerParams<F>
 where
     F: Fn(OrganizeEvent) -> bool,
@@ -112,19 +374,101 @@ where
     unorganized_dir: PathBuf,
     organized_dir: PathBuf,
     duplicates_dir: PathBuf,
+    undated_dir: PathBuf,
 
+    options: OrganizeOptions,
     event_handler: F,
 }
 
+#[derive(Default)]
 struct OrganizeCounters {
-    dirs: Cell<u64>,
-    dirs_skipped: Cell<u64>,
-    files: Cell<u64>,
-    files_skipped: Cell<u64>,
-    files_errored: Cell<u64>,
-    photos_moved: Cell<u64>,
-    duplicate_photos_moved: Cell<u64>,
-    photos_noop: Cell<u64>,
+    dirs: AtomicU64,
+    dirs_skipped: AtomicU64,
+    files: AtomicU64,
+    files_skipped: AtomicU64,
+    files_errored: AtomicU64,
+    files_unverified: AtomicU64,
+    photos_moved: AtomicU64,
+    photos_copied: AtomicU64,
+    duplicate_photos_moved: AtomicU64,
+    photos_noop: AtomicU64,
+    photos_undated: AtomicU64,
+    sidecars_moved: AtomicU64,
+    sidecars_copied: AtomicU64,
+    sidecars_orphaned: AtomicU64,
+}
+
+/// A file that has been read by a worker and is ready to be organized.
+#[doc(hidden)]
+enum PreparedFile {
+    /// The file is not organized, for the given reason.
+    Skipped(&'static str),
+    /// The file is a photo, or there was an error reading it.
+    Photo(anyhow::Result<Box<PreparedPhoto>>),
+    /// The file is a sidecar of a photo, or there was an error reading it.
+    Sidecar(anyhow::Result<PreparedSidecar>),
+}
+
+/// The information about a photo needed to organize it.
+#[doc(hidden)]
+struct PreparedPhoto {
+    /// The date-time the photo was taken, in the time zone used for paths, and where it came from. None when the photo
+    /// does not have a plausible date-time.
+    date: Option<(NaiveDateTime, DateSource)>,
+    /// The clock correction applied to the date-time, if any.
+    correction: Option<ClockCorrection>,
+    /// The format of the photo, recognized by its contents.
+    file_type: FileType,
+    /// The extension the photo is placed with.
+    extension: String,
+    camera: CameraInfo,
+    hash: String,
+    len: u64,
+}
+
+/// The information about a sidecar file (e.g. an XMP file) needed to move it with its photo.
+#[doc(hidden)]
+struct PreparedSidecar {
+    hash: String,
+    len: u64,
+}
+
+/// A sidecar file in a group, with the index of its photo.
+#[doc(hidden)]
+struct GroupSidecar<'a> {
+    file_path: &'a Path,
+    photo: usize,
+    sidecar: PreparedSidecar,
+}
+
+/// What is at the organized location of a photo.
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq)]
+enum Destination {
+    /// The photo is already at its organized location.
+    InPlace,
+    /// There is no file at the location.
+    Free,
+    /// There is a file with the same contents as the photo at the location.
+    Identical,
+}
+
+/// A file of a group that has been placed: the index of its photo, how it was placed, where it came from and went,
+/// and its hash.
+#[doc(hidden)]
+type PlacedFile<'a> = (usize, JournalKind, &'a Path, PathBuf, String);
+
+/// The organized path of each photo in a group and what is there.
+#[doc(hidden)]
+type GroupDestinations = Vec<(PathBuf, Destination)>;
+
+/// The destination as it would look after the moves planned by a dry run.
+#[derive(Default)]
+struct SimulatedDestination {
+    /// The planned destination paths, mapped to the hash of the file that would be moved there.
+    planned: HashMap<PathBuf, String>,
+    /// The source files that would have been moved away.
+    vacated: HashSet<PathBuf>,
 }
 
 #[doc(hidden)]
@@ -137,9 +481,13 @@ where
     unorganized_dir: PathBuf,
     organized_dir: PathBuf,
     duplicates_dir: PathBuf,
+    undated_dir: PathBuf,
 
+    pool: rayon::ThreadPool,
     counters: OrganizeCounters,
-    canceled: Cell<bool>,
+    canceled: AtomicBool,
+    simulated: RefCell<SimulatedDestination>,
+    journal: RefCell<Option<Journal>>,
 }
 
 #[doc(hidden)]
@@ -152,11 +500,14 @@ where
         unorganized_dir: &Path,
         organized_dir: &Path,
         duplicates_dir: &Path,
+        undated_dir: &Path,
+        options: OrganizeOptions,
         event_handler: F,
     ) -> anyhow::Result<Organizer<F>> {
-        let canonical_unorganized_dir = fs::canonicalize(unorganized_dir)?;
-        let canonical_organized_dir = fs::canonicalize(organized_dir)?;
-        let canonical_duplicates_dir = fs::canonicalize(duplicates_dir)?;
+        let canonical_unorganized_dir = canonicalize_dir(unorganized_dir)?;
+        let canonical_organized_dir = canonicalize_dir(organized_dir)?;
+        let canonical_duplicates_dir = canonicalize_dir(duplicates_dir)?;
+        let canonical_undated_dir = canonicalize_dir(undated_dir)?;
 
         ensure!(
             canonical_unorganized_dir != canonical_duplicates_dir,
@@ -166,60 +517,100 @@ where
             canonical_organized_dir != canonical_duplicates_dir,
             "The organized directory and duplicates directory cannot be the the same directory."
         );
+        ensure!(
+            ![
+                &canonical_unorganized_dir,
+                &canonical_organized_dir,
+                &canonical_duplicates_dir
+            ]
+            .contains(&&canonical_undated_dir),
+            "The undated directory cannot be the same directory as the unorganized, organized, or duplicates directory."
+        );
+
+        // a dry run does not move anything, so there is nothing to journal
+        let journal = if options.dry_run {
+            None
+        } else {
+            Some(Journal::open(&canonical_organized_dir, &new_run_id())?)
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(options.jobs)
+            .build()?;
 
         Ok(Organizer {
             params: OrganizerParams {
                 unorganized_dir: unorganized_dir.to_path_buf(),
                 organized_dir: organized_dir.to_path_buf(),
                 duplicates_dir: duplicates_dir.to_path_buf(),
+                undated_dir: undated_dir.to_path_buf(),
+                options,
                 event_handler,
             },
             unorganized_dir: canonical_unorganized_dir,
             organized_dir: canonical_organized_dir,
             duplicates_dir: canonical_duplicates_dir,
-            counters: OrganizeCounters {
-                dirs: Cell::new(0),
-                dirs_skipped: Cell::new(0),
-                files: Cell::new(0),
-                photos_moved: Cell::new(0),
-                duplicate_photos_moved: Cell::new(0),
-                photos_noop: Cell::new(0),
-                files_skipped: Cell::new(0),
-                files_errored: Cell::new(0),
-            },
-            canceled: Cell::new(false),
+            undated_dir: canonical_undated_dir,
+            pool,
+            counters: OrganizeCounters::default(),
+            canceled: AtomicBool::new(false),
+            simulated: RefCell::new(SimulatedDestination::default()),
+            journal: RefCell::new(journal),
         })
     }
 
     /// Organize the unorganized directory of photos, placing photos to their organized location.
     /// Any duplicate photos are moved to the duplicates directory.
     pub fn organize(&self) -> anyhow::Result<OrganizeResult> {
-        self.canceled.set(false);
+        self.canceled.store(false, Ordering::SeqCst);
 
         let timer = Instant::now();
-        self.organize_directory((&self).unorganized_dir.as_ref())?;
+        if self.unorganized_dir.exists() {
+            self.organize_directory(self.unorganized_dir.as_ref())?;
+        }
         let duration = timer.elapsed();
 
         Ok(OrganizeResult {
-            dirs: self.counters.dirs.get(),
-            dirs_skipped: self.counters.dirs_skipped.get(),
-            files: self.counters.files.get(),
-            photos_moved: self.counters.photos_moved.get(),
-            duplicate_photos_moved: self.counters.duplicate_photos_moved.get(),
-            photos_noop: self.counters.photos_noop.get(),
-            files_skipped: self.counters.files_skipped.get(),
-            files_errored: self.counters.files_errored.get(),
+            run_id: self
+                .journal
+                .borrow()
+                .as_ref()
+                .map(|journal| journal.run_id().to_string()),
+            dirs: self.counters.dirs.load(Ordering::SeqCst),
+            dirs_skipped: self.counters.dirs_skipped.load(Ordering::SeqCst),
+            files: self.counters.files.load(Ordering::SeqCst),
+            photos_moved: self.counters.photos_moved.load(Ordering::SeqCst),
+            photos_copied: self.counters.photos_copied.load(Ordering::SeqCst),
+            duplicate_photos_moved: self.counters.duplicate_photos_moved.load(Ordering::SeqCst),
+            photos_noop: self.counters.photos_noop.load(Ordering::SeqCst),
+            photos_undated: self.counters.photos_undated.load(Ordering::SeqCst),
+            sidecars_moved: self.counters.sidecars_moved.load(Ordering::SeqCst),
+            sidecars_copied: self.counters.sidecars_copied.load(Ordering::SeqCst),
+            sidecars_orphaned: self.counters.sidecars_orphaned.load(Ordering::SeqCst),
+            files_skipped: self.counters.files_skipped.load(Ordering::SeqCst),
+            files_errored: self.counters.files_errored.load(Ordering::SeqCst),
+            files_unverified: self.counters.files_unverified.load(Ordering::SeqCst),
             duration,
         })
     }
 
     fn organize_directory(&self, dir: &Path) -> anyhow::Result<()> {
+        if self.is_canceled() {
+            return Ok(());
+        }
+
         // do not process the duplicates directory
         if dir == self.duplicates_dir {
             self.raise_dir_skipped(dir, "Directory is the duplicates directory.");
             return Ok(());
         }
 
+        // do not process the undated directory, whose photos would only be undated again
+        if dir == self.undated_dir {
+            self.raise_dir_skipped(dir, "Directory is the undated directory.");
+            return Ok(());
+        }
+
         self.raise_dir_started(dir);
 
         let mut entries = fs::read_dir(dir)?
@@ -227,16 +618,40 @@ where
             .collect::<Result<Vec<_>, io::Error>>()?;
         entries.sort();
 
-        // organize files in this directory
-        for e in entries.iter().filter(|e| e.is_file()) {
-            match self.organize_file(&e) {
-                Ok(_) => {}
-                Err(err) => self.raise_file_error(&e, err),
-            }
+        let files: Vec<&PathBuf> = entries.iter().filter(|e| e.is_file()).collect();
+
+        // do not read or hash the files when the run was canceled as the directory started
+        if self.is_canceled() {
+            return Ok(());
+        }
+
+        // read the date-times and hashes of the files in parallel
+        let options = &self.params.options;
+        let prepared: Vec<PreparedFile> = self.pool.install(|| {
+            files
+                .par_iter()
+                .map(|file| prepare_file(file, options))
+                .collect()
+        });
+
+        // organize files in this directory, in order, so that conflict numbers are deterministic.
+        // companion files (e.g. RAW+JPEG pairs and Live Photos) are organized together.
+        let groups = group_companions(&files, &prepared);
+        let mut prepared: Vec<Option<PreparedFile>> = prepared.into_iter().map(Some).collect();
+        for group in groups {
+            let members = group
+                .into_iter()
+                .filter_map(|index| Some((files[index].as_path(), prepared[index].take()?)))
+                .collect();
+            self.organize_group(members);
         }
 
         // organize child directories
         for e in entries.iter().filter(|e| e.is_dir()) {
+            if self.is_canceled() {
+                return Ok(());
+            }
+
             self.organize_directory(&e)?;
         }
 
@@ -245,122 +660,571 @@ where
         Ok(())
     }
 
-    fn organize_file(&self, file_path: &Path) -> anyhow::Result<()> {
-        self.raise_file_started(file_path);
+    /// Organizes a file, or a group of companion files which are given the same name and conflict number, along with
+    /// their sidecar files.
+    fn organize_group(&self, members: Vec<(&Path, PreparedFile)>) {
+        for (file_path, _) in &members {
+            self.raise_file_started(file_path);
+        }
 
-        if self.canceled.get() {
-            return Ok(());
+        if self.is_canceled() {
+            return;
         }
 
-        // only handle files with photo extensions
-        if !is_photo_file(file_path) {
-            self.raise_file_skipped(file_path, "File does not have a photo extension.");
-            return Ok(());
+        let has_photos = members
+            .iter()
+            .any(|(_, prepared)| !matches!(prepared, PreparedFile::Sidecar(_)));
+
+        let mut photos = Vec::new();
+        let mut sidecar_members = Vec::new();
+        let mut has_unread_photo = false;
+        for (file_path, prepared) in members {
+            match prepared {
+                PreparedFile::Skipped(reason) => self.raise_file_skipped(file_path, reason),
+                PreparedFile::Photo(Err(err)) => {
+                    has_unread_photo = true;
+                    self.raise_organize_error(file_path, err);
+                }
+                PreparedFile::Photo(Ok(photo)) => photos.push((file_path, photo)),
+                PreparedFile::Sidecar(Err(err)) => self.raise_organize_error(file_path, err),
+                PreparedFile::Sidecar(Ok(sidecar)) => sidecar_members.push((file_path, sidecar)),
+            }
         }
 
-        // never move a file with ! in the name
-        if file_path
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .contains("!")
+        // each sidecar follows the photo it is named after
+        let mut sidecars = Vec::new();
+        for (file_path, sidecar) in sidecar_members {
+            match sidecar_photo_index(file_path, &photos) {
+                Some(photo) => sidecars.push(GroupSidecar {
+                    file_path,
+                    photo,
+                    sidecar,
+                }),
+                None if has_photos => self.raise_file_skipped(
+                    file_path,
+                    "The photo of this sidecar file was not organized.",
+                ),
+                None => self.raise_sidecar_orphaned(file_path),
+            }
+        }
+
+        // a group is moved as a whole, so the companions of a photo that could not be read are left in place with it
+        if has_unread_photo {
+            for (file_path, _) in &photos {
+                self.raise_file_skipped(file_path, "A companion file could not be organized.");
+            }
+            for sidecar in &sidecars {
+                self.raise_file_skipped(
+                    sidecar.file_path,
+                    "The photo of this sidecar file was not organized.",
+                );
+            }
+            return;
+        }
+
+        if photos.is_empty() {
+            return;
+        }
+
+        // companions without a date-time of their own are dated by the primary photo, or the first with one
+        let primary = primary_photo_index(&photos);
+        let group_date = photos[primary]
+            .1
+            .date
+            .or_else(|| photos.iter().find_map(|(_, photo)| photo.date));
+        for (_, photo) in photos.iter_mut() {
+            photo.date = photo.date.or(group_date);
+        }
+
+        let destinations = match self.place_photos(&photos, &sidecars) {
+            Ok(Some(destinations)) => destinations,
+            Ok(None) => return,
+            Err((index, err)) => {
+                // none of the group was moved, so the companions are left in place with the photo that failed
+                for (other, (file_path, _)) in photos.iter().enumerate() {
+                    if other != index {
+                        self.raise_file_skipped(
+                            file_path,
+                            "A companion file could not be organized.",
+                        );
+                    }
+                }
+                self.raise_organize_error(photos[index].0, err);
+                for sidecar in &sidecars {
+                    self.raise_file_skipped(
+                        sidecar.file_path,
+                        "The photo of this sidecar file was not organized.",
+                    );
+                }
+                return;
+            }
+        };
+
+        for (index, ((file_path, photo), (dest_path, destination))) in
+            photos.iter().zip(&destinations).enumerate()
         {
-            self.raise_file_skipped(file_path, "File name contains '!'.");
-            return Ok(());
+            let photo_sidecars: Vec<&GroupSidecar> =
+                sidecars.iter().filter(|s| s.photo == index).collect();
+
+            let placed_path =
+                match self.finish_photo(file_path, photo, dest_path, *destination, &photo_sidecars)
+                {
+                    Ok(placed_path) => {
+                        self.raise_file_finished(file_path);
+                        placed_path
+                    }
+                    Err(err) => {
+                        self.raise_organize_error(file_path, err);
+                        None
+                    }
+                };
+
+            for sidecar in photo_sidecars {
+                match &placed_path {
+                    // the sidecars of a placed photo were placed with it
+                    Some(placed_path) if *destination == Destination::Free => {
+                        let sidecar_path =
+                            get_sidecar_path(sidecar.file_path, file_path, placed_path);
+                        if sidecar.file_path.to_str() != sidecar_path.to_str() {
+                            match self.params.options.mode {
+                                OrganizeMode::Copy => {
+                                    self.raise_sidecar_copied(sidecar.file_path, &sidecar_path)
+                                }
+                                OrganizeMode::Move => {
+                                    self.raise_sidecar_moved(sidecar.file_path, &sidecar_path)
+                                }
+                            }
+                        }
+                        self.raise_file_finished(sidecar.file_path);
+                    }
+                    Some(placed_path) => {
+                        match self.organize_sidecar(sidecar, file_path, placed_path) {
+                            Ok(_) => self.raise_file_finished(sidecar.file_path),
+                            Err(err) => self.raise_organize_error(sidecar.file_path, err),
+                        }
+                    }
+                    None => self.raise_file_skipped(
+                        sidecar.file_path,
+                        "The photo of this sidecar file was not organized.",
+                    ),
+                }
+            }
+
+            if self.is_canceled() {
+                return;
+            }
         }
+    }
 
-        let photo_date_time_info = PhotoDateTimeInfo::load(file_path)?;
-        let photo_date_time = photo_date_time_info.best();
+    /// Finds the destinations of a group of photos and moves (or copies) the photos that are not there yet, along with
+    /// the sidecars of the photos that are placed.
+    ///
+    /// The group is named after its primary photo and shares one conflict number, so each photo keeps the name of its
+    /// companions. Either all of the photos and sidecars are placed or, when one cannot be, none of them are; the error
+    /// is returned with the index of the photo that failed, or of the photo of the sidecar that failed. None is returned when organizing is canceled.
+    fn place_photos(
+        &self,
+        photos: &[(&Path, Box<PreparedPhoto>)],
+        sidecars: &[GroupSidecar],
+    ) -> Result<Option<GroupDestinations>, (usize, anyhow::Error)> {
+        let (primary_path, primary) = &photos[primary_photo_index(photos)];
 
         let mut conflict = 0;
-        loop {
+        let destinations = loop {
             // check for cancellation at the start of each iteration
-            if self.canceled.get() {
-                return Ok(());
+            if self.is_canceled() {
+                return Ok(None);
             }
 
-            let dest_path = get_organized_photo_path(
-                file_path,
-                &photo_date_time,
-                conflict,
-                &self.organized_dir,
-            )?;
+            let mut destinations = Vec::new();
+            for (index, (file_path, photo)) in photos.iter().enumerate() {
+                let dest_path = match primary.date {
+                    Some(_) => get_organized_photo_path(
+                        primary_path,
+                        &photo.extension,
+                        primary,
+                        conflict,
+                        &self.organized_dir,
+                        &self.params.options.organized_template,
+                    )
+                    .map_err(|err| (index, err))?,
+                    None => get_undated_photo_path(
+                        file_path,
+                        photo,
+                        conflict,
+                        &self.unorganized_dir,
+                        &self.undated_dir,
+                    ),
+                };
+
+                // the sidecars of a photo that is placed need to be free as well
+                match self
+                    .get_destination(file_path, &dest_path, photo)
+                    .map_err(|err| (index, err))?
+                {
+                    Some(Destination::Free)
+                        if !self.sidecars_are_free(
+                            sidecars.iter().filter(|s| s.photo == index),
+                            file_path,
+                            &dest_path,
+                        ) =>
+                    {
+                        break
+                    }
+                    Some(destination) => destinations.push((dest_path, destination)),
+                    None => break,
+                }
+            }
 
-            // if the file is already in the right place, do nothing
-            if file_path.to_str() == dest_path.to_str() {
-                self.raise_file_noop(file_path);
+            // if there is a different file at any of the destinations, try again with a higher conflict number
+            if destinations.len() == photos.len() {
+                break destinations;
+            }
+            conflict += 1;
+        };
+
+        let copy = self.params.options.mode == OrganizeMode::Copy;
+        let (photo_kind, sidecar_kind) = if copy {
+            (JournalKind::PhotoCopied, JournalKind::SidecarCopied)
+        } else {
+            (JournalKind::PhotoMoved, JournalKind::SidecarMoved)
+        };
+
+        let mut placed: Vec<PlacedFile> = Vec::new();
+        for (index, ((file_path, photo), (dest_path, destination))) in
+            photos.iter().zip(&destinations).enumerate()
+        {
+            if *destination != Destination::Free {
+                continue;
+            }
 
-                if self.canceled.get() {
-                    return Ok(());
-                }
+            match self.place_file(file_path, dest_path, &photo.hash) {
+                Ok(hash) => placed.push((index, photo_kind, file_path, dest_path.clone(), hash)),
+                Err(err) => return Err((index, self.put_back(&placed, err))),
+            }
+        }
 
-                break;
+        // the sidecars of the placed photos are placed with the group, so they are put back with it
+        for sidecar in sidecars {
+            let (dest_path, destination) = &destinations[sidecar.photo];
+            let sidecar_path =
+                get_sidecar_path(sidecar.file_path, photos[sidecar.photo].0, dest_path);
+            if *destination != Destination::Free
+                || sidecar.file_path.to_str() == sidecar_path.to_str()
+            {
+                continue;
             }
 
-            // if there is already a file in this location,
-            if dest_path.exists() {
-                match are_same_file_contents(file_path, &dest_path)? {
-                    Some(hash) => {
-                        self.organize_duplicate(file_path, &photo_date_time, &hash)?;
-                        break;
-                    }
-                    None => {
-                        // if there is a different file in this location, try again with a higher conflict number
-                        conflict += 1;
-                        continue;
-                    }
+            match self.place_file(sidecar.file_path, &sidecar_path, &sidecar.sidecar.hash) {
+                Ok(hash) => placed.push((
+                    sidecar.photo,
+                    sidecar_kind,
+                    sidecar.file_path,
+                    sidecar_path,
+                    hash,
+                )),
+                Err(err) => {
+                    let err = err.context(format!(
+                        "The sidecar file {:?} could not be placed with its photo.",
+                        sidecar.file_path
+                    ));
+                    return Err((sidecar.photo, self.put_back(&placed, err)));
                 }
-            } else {
-                // move the file to the destination
-                move_file(file_path, dest_path.as_ref())?;
-                self.raise_file_moved(file_path, &dest_path);
-                break;
             }
         }
 
-        self.raise_file_finished(file_path);
+        // the group is only journaled once all of it is placed, in one append so that none of it is journaled when
+        // the append fails, and is then put back since undo could not reverse it
+        let changes: Vec<(JournalKind, &Path, &Path, &str)> = placed
+            .iter()
+            .map(|(_, kind, from, to, hash)| (*kind, *from, to.as_path(), hash.as_str()))
+            .collect();
+        if let Err(err) = self.journal_all(&changes) {
+            return Err((primary_photo_index(photos), self.put_back(&placed, err)));
+        }
 
-        Ok(())
+        Ok(Some(destinations))
+    }
+
+    /// Moves (or copies) a file of a group, and returns the hash of the placed file.
+    fn place_file(&self, from: &Path, to: &Path, hash: &str) -> anyhow::Result<String> {
+        if self.params.options.mode == OrganizeMode::Copy {
+            self.copy_file(from, to, hash)
+        } else {
+            self.move_file(from, to, hash).map(|_| hash.to_string())
+        }
     }
 
+    /// Puts back the photos and sidecars of a group that were placed before another file of the group failed, or when
+    /// the group could not be journaled.
+    /// A file that cannot be put back is journaled, so that the run can still be undone.
+    fn put_back(&self, placed: &[PlacedFile], mut err: anyhow::Error) -> anyhow::Error {
+        for (_, kind, from, to, hash) in placed.iter().rev() {
+            let kind = *kind;
+            let result = match kind {
+                JournalKind::PhotoCopied | JournalKind::SidecarCopied => {
+                    fs::remove_file(to).map_err(anyhow::Error::from)
+                }
+                _ => move_file(to, from),
+            };
+
+            if let Err(put_back_err) = result {
+                err = err.context(format!(
+                    "Unable to put back {:?} after the group failed: {}",
+                    to, put_back_err
+                ));
+                if let Err(journal_err) = self.journal(kind, from, to, hash) {
+                    err = err.context(journal_err);
+                }
+            }
+        }
+
+        err
+    }
+
+    /// Raises the event for a photo of a group that has been placed, or moves it to the duplicates directory when it
+    /// matched an existing photo. Returns where the photo ended up, for its sidecars to follow, or None when organizing
+    /// is canceled.
+    fn finish_photo(
+        &self,
+        file_path: &Path,
+        photo: &PreparedPhoto,
+        dest_path: &Path,
+        destination: Destination,
+        sidecars: &[&GroupSidecar],
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let copy = self.params.options.mode == OrganizeMode::Copy;
+
+        match destination {
+            Destination::InPlace => self.raise_file_noop(file_path),
+            Destination::Free if photo.date.is_none() => {
+                self.raise_photo_undated(file_path, dest_path)
+            }
+            Destination::Free if copy => self.raise_file_copied(file_path, dest_path, photo),
+            Destination::Free => self.raise_file_moved(file_path, dest_path, photo),
+            // when copying, an identical file means the photo was already imported
+            Destination::Identical if copy => self.raise_file_noop(file_path),
+            Destination::Identical => {
+                return self.organize_duplicate(file_path, photo, dest_path, sidecars)
+            }
+        }
+
+        Ok(Some(dest_path.to_path_buf()))
+    }
+
+    /// Moves a photo that is a duplicate of the organized photo at `matched_path` to the duplicates directory.
+    /// Returns where the duplicate ended up, or None when organizing is canceled.
     fn organize_duplicate(
         &self,
         file_path: &Path,
-        date_time: &chrono::DateTime<Utc>,
-        hash: &str,
-    ) -> anyhow::Result<()> {
+        photo: &PreparedPhoto,
+        matched_path: &Path,
+        sidecars: &[&GroupSidecar],
+    ) -> anyhow::Result<Option<PathBuf>> {
         let mut conflict = 0;
         loop {
             // check for cancellation at the start of each iteration
-            if self.canceled.get() {
-                return Ok(());
+            if self.is_canceled() {
+                return Ok(None);
             }
 
-            let dest_path = get_duplicate_photo_path(
-                file_path,
-                date_time,
-                hash,
-                conflict,
-                &self.duplicates_dir,
-            )?;
+            let dest_path = match photo.date {
+                Some(_) => get_duplicate_photo_path(
+                    file_path,
+                    photo,
+                    matched_path,
+                    conflict,
+                    &self.duplicates_dir,
+                    &self.params.options.duplicates_template,
+                )?,
+                None => get_undated_photo_path(
+                    file_path,
+                    photo,
+                    conflict,
+                    &self.unorganized_dir,
+                    &self.duplicates_dir.join(UNDATED_DIR_NAME),
+                ),
+            };
 
             // if the duplicate is already in the right place, do nothing
             if file_path.to_str() == dest_path.to_str() {
                 self.raise_file_noop(file_path);
-                break;
+                return Ok(Some(dest_path));
             }
 
-            // if there is an existing duplicate, try again with a higher conflict number
-            if dest_path.exists() {
+            // if there is an existing duplicate (or sidecar), try again with a higher conflict number
+            if self.destination_exists(&dest_path)
+                || !self.sidecars_are_free(sidecars.iter().copied(), file_path, &dest_path)
+            {
                 conflict += 1;
                 continue;
             }
 
             // move the duplicate to the destination
-            move_file(file_path, &dest_path)?;
-            self.raise_duplicate_moved(file_path, &dest_path);
-            break;
+            self.move_file(file_path, &dest_path, &photo.hash)?;
+            self.journal(
+                JournalKind::DuplicatePhotoMoved,
+                file_path,
+                &dest_path,
+                &photo.hash,
+            )?;
+            self.raise_duplicate_moved(file_path, &dest_path, photo);
+            return Ok(Some(dest_path));
+        }
+    }
+
+    /// Moves (or copies) a sidecar file next to where its photo ended up, named to match.
+    fn organize_sidecar(
+        &self,
+        sidecar: &GroupSidecar,
+        photo_path: &Path,
+        placed_path: &Path,
+    ) -> anyhow::Result<()> {
+        let dest_path = get_sidecar_path(sidecar.file_path, photo_path, placed_path);
+
+        // if the sidecar is already in the right place, do nothing
+        if sidecar.file_path.to_str() == dest_path.to_str() {
+            return Ok(());
+        }
+
+        let copy = self.params.options.mode == OrganizeMode::Copy;
+
+        if self.destination_exists(&dest_path) {
+            // when copying, an identical sidecar means it was already imported with its photo
+            ensure!(
+                copy && self.destination_has_contents(
+                    &dest_path,
+                    &sidecar.sidecar.hash,
+                    sidecar.sidecar.len
+                )?,
+                "The sidecar file cannot be placed with its photo because {:?} already exists.",
+                self.decry_destination_path(&dest_path)
+            );
+            return Ok(());
+        }
+
+        if copy {
+            let hash = self.copy_file(sidecar.file_path, &dest_path, &sidecar.sidecar.hash)?;
+            self.journal(
+                JournalKind::SidecarCopied,
+                sidecar.file_path,
+                &dest_path,
+                &hash,
+            )?;
+            self.raise_sidecar_copied(sidecar.file_path, &dest_path);
+        } else {
+            self.move_file(sidecar.file_path, &dest_path, &sidecar.sidecar.hash)?;
+            self.journal(
+                JournalKind::SidecarMoved,
+                sidecar.file_path,
+                &dest_path,
+                &sidecar.sidecar.hash,
+            )?;
+            self.raise_sidecar_moved(sidecar.file_path, &dest_path);
+        }
+
+        Ok(())
+    }
+
+    // -------------------- Destination --------------------//
+
+    /// Determines what is at the destination of a photo, or None when it is a different file.
+    fn get_destination(
+        &self,
+        file_path: &Path,
+        dest_path: &Path,
+        photo: &PreparedPhoto,
+    ) -> anyhow::Result<Option<Destination>> {
+        if file_path.to_str() == dest_path.to_str() {
+            return Ok(Some(Destination::InPlace));
+        }
+
+        if !self.destination_exists(dest_path) {
+            return Ok(Some(Destination::Free));
+        }
+
+        if self.destination_has_contents(dest_path, &photo.hash, photo.len)? {
+            return Ok(Some(Destination::Identical));
+        }
+
+        Ok(None)
+    }
+
+    /// Determines if the sidecars of a photo can be placed next to it at `dest_path`.
+    fn sidecars_are_free<'s>(
+        &self,
+        mut sidecars: impl Iterator<Item = &'s GroupSidecar<'s>>,
+        photo_path: &Path,
+        dest_path: &Path,
+    ) -> bool {
+        sidecars.all(|sidecar| {
+            let sidecar_path = get_sidecar_path(sidecar.file_path, photo_path, dest_path);
+            sidecar.file_path.to_str() == sidecar_path.to_str()
+                || !self.destination_exists(&sidecar_path)
+        })
+    }
+
+    /// Determines if a file exists at the path, including the moves planned by a dry run.
+    fn destination_exists(&self, path: &Path) -> bool {
+        if self.params.options.dry_run {
+            let simulated = self.simulated.borrow();
+            if simulated.planned.contains_key(path) {
+                return true;
+            }
+            if simulated.vacated.contains(path) {
+                return false;
+            }
+        }
+
+        path.exists()
+    }
+
+    /// Determines if the file at the path has the given hash and length, including the moves planned by a dry run.
+    fn destination_has_contents(&self, path: &Path, hash: &str, len: u64) -> anyhow::Result<bool> {
+        if self.params.options.dry_run {
+            if let Some(planned_hash) = self.simulated.borrow().planned.get(path) {
+                return Ok(planned_hash == hash);
+            }
+        }
+
+        is_same_file_contents(path, hash, len)
+    }
+
+    /// Moves the file, or plans the move when this is a dry run.
+    fn move_file(&self, from: &Path, to: &Path, hash: &str) -> anyhow::Result<()> {
+        if self.params.options.dry_run {
+            let mut simulated = self.simulated.borrow_mut();
+            simulated.planned.insert(to.to_path_buf(), hash.to_string());
+            simulated.vacated.insert(from.to_path_buf());
+            return Ok(());
+        }
+
+        move_file(from, to)
+    }
+
+    /// Copies the file and returns the hash of the copy, or plans the copy when this is a dry run.
+    fn copy_file(&self, from: &Path, to: &Path, hash: &str) -> anyhow::Result<String> {
+        if self.params.options.dry_run {
+            let mut simulated = self.simulated.borrow_mut();
+            simulated.planned.insert(to.to_path_buf(), hash.to_string());
+            return Ok(hash.to_string());
+        }
+
+        copy_file(from, to)
+    }
+
+    /// Appends a move or copy to the journal. A dry run has no journal.
+    fn journal(&self, kind: JournalKind, from: &Path, to: &Path, hash: &str) -> anyhow::Result<()> {
+        if let Some(journal) = self.journal.borrow_mut().as_mut() {
+            journal.append(kind, from, to, hash)?;
+        }
+
+        Ok(())
+    }
+
+    fn journal_all(&self, changes: &[(JournalKind, &Path, &Path, &str)]) -> anyhow::Result<()> {
+        if let Some(journal) = self.journal.borrow_mut().as_mut() {
+            journal.append_all(changes)?;
         }
 
         Ok(())
@@ -402,11 +1266,25 @@ where
         });
     }
 
-    fn raise_file_moved(&self, from: &Path, to: &Path) {
+    fn raise_file_moved(&self, from: &Path, to: &Path, photo: &PreparedPhoto) {
         increment(&self.counters.photos_moved);
         self.on_event(OrganizeEvent::PhotoMoved {
             from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
             to: &decry_path(to, &self.organized_dir, &self.params.organized_dir),
+            simulated: self.params.options.dry_run,
+            date_source: photo.date.map(|(_, date_source)| date_source),
+            correction: photo.correction.as_ref(),
+        });
+    }
+
+    fn raise_file_copied(&self, from: &Path, to: &Path, photo: &PreparedPhoto) {
+        increment(&self.counters.photos_copied);
+        self.on_event(OrganizeEvent::PhotoCopied {
+            from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
+            to: &decry_path(to, &self.organized_dir, &self.params.organized_dir),
+            simulated: self.params.options.dry_run,
+            date_source: photo.date.map(|(_, date_source)| date_source),
+            correction: photo.correction.as_ref(),
         });
     }
 
@@ -417,11 +1295,48 @@ where
         });
     }
 
-    fn raise_duplicate_moved(&self, from: &Path, to: &Path) {
+    fn raise_duplicate_moved(&self, from: &Path, to: &Path, photo: &PreparedPhoto) {
         increment(&self.counters.duplicate_photos_moved);
         self.on_event(OrganizeEvent::DuplicatePhotoMoved {
             from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
             to: &decry_path(to, &self.duplicates_dir, &self.params.duplicates_dir),
+            simulated: self.params.options.dry_run,
+            date_source: photo.date.map(|(_, date_source)| date_source),
+            correction: photo.correction.as_ref(),
+        });
+    }
+
+    fn raise_photo_undated(&self, from: &Path, to: &Path) {
+        increment(&self.counters.photos_undated);
+        self.on_event(OrganizeEvent::PhotoUndated {
+            from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
+            to: &self.decry_destination_path(to),
+            simulated: self.params.options.dry_run,
+        });
+    }
+
+    fn raise_sidecar_moved(&self, from: &Path, to: &Path) {
+        increment(&self.counters.sidecars_moved);
+        self.on_event(OrganizeEvent::SidecarMoved {
+            from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
+            to: &self.decry_destination_path(to),
+            simulated: self.params.options.dry_run,
+        });
+    }
+
+    fn raise_sidecar_copied(&self, from: &Path, to: &Path) {
+        increment(&self.counters.sidecars_copied);
+        self.on_event(OrganizeEvent::SidecarCopied {
+            from: &decry_path(from, &self.unorganized_dir, &self.params.unorganized_dir),
+            to: &decry_path(to, &self.organized_dir, &self.params.organized_dir),
+            simulated: self.params.options.dry_run,
+        });
+    }
+
+    fn raise_sidecar_orphaned(&self, file: &Path) {
+        increment(&self.counters.sidecars_orphaned);
+        self.on_event(OrganizeEvent::SidecarOrphaned {
+            file: &decry_path(file, &self.unorganized_dir, &self.params.unorganized_dir),
         });
     }
 
@@ -441,144 +1356,491 @@ where
         });
     }
 
+    fn raise_file_verification_failed(&self, file: &Path, error: &VerificationError) {
+        increment(&self.counters.files_unverified);
+        self.on_event(OrganizeEvent::FileVerificationFailed {
+            file: &decry_path(file, &self.unorganized_dir, &self.params.unorganized_dir),
+            copy: &self.decry_destination_path(&error.to),
+            expected_hash: &error.expected_hash,
+            actual_hash: &error.actual_hash,
+        });
+    }
+
+    /// Raises the error of a file, which is a verification failure when a copy of the file did not match it.
+    fn raise_organize_error(&self, file: &Path, err: anyhow::Error) {
+        match err.downcast::<VerificationError>() {
+            Ok(verification_error) => {
+                self.raise_file_verification_failed(file, &verification_error)
+            }
+            Err(err) => self.raise_file_error(file, err),
+        }
+    }
+
+    /// Decries a path in either the organized or duplicates directory.
+    fn decry_destination_path(&self, path: &Path) -> PathBuf {
+        if path.starts_with(&self.organized_dir) {
+            decry_path(path, &self.organized_dir, &self.params.organized_dir)
+        } else if path.starts_with(&self.undated_dir) {
+            decry_path(path, &self.undated_dir, &self.params.undated_dir)
+        } else {
+            decry_path(path, &self.duplicates_dir, &self.params.duplicates_dir)
+        }
+    }
+
     fn on_event(&self, event: OrganizeEvent) {
         if !(self.params.event_handler)(event) {
-            self.canceled.set(true);
+            self.canceled.store(true, Ordering::SeqCst);
         }
     }
+
+    fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
 }
 
-/// Determines if a file is a photo by inspecting the extension
+/// Reads the information needed to organize a file. This is called by the workers, in parallel.
 #[doc(hidden)]
-fn is_photo_file(path: &Path) -> bool {
-    if path.exists() && path.is_file() {
-        if let Some(ext) = path.extension() {
-            if let Some(str_ext) = ext.to_str() {
-                match str_ext.to_lowercase().as_str() {
-                    "bmp" => return true,
-                    "gif" => return true,
-                    "jpg" => return true,
-                    "jpeg" => return true,
-                    "png" => return true,
-                    "tif" => return true,
-                    "tiff" => return true,
-                    "wmp" => return true,
-                    _ => return false,
-                }
+fn prepare_file(file_path: &Path, options: &OrganizeOptions) -> PreparedFile {
+    // never move a file with ! in the name
+    if file_path
+        .file_stem()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains('!')
+    {
+        return PreparedFile::Skipped("File name contains '!'.");
+    }
+
+    let is_sidecar = is_sidecar_file(file_path, &options.sidecar_extensions);
+    let extension = lowercase_extension(file_path);
+
+    // only handle sidecars and files whose contents are a photo or video, whatever their extension
+    let file_type = if is_sidecar {
+        None
+    } else if is_non_photo_extension(&extension) {
+        return PreparedFile::Skipped("File is not a photo or video.");
+    } else {
+        match detect_file_type(file_path) {
+            Ok(Some(file_type)) => Some(file_type),
+            Ok(None) if is_photo_extension(&extension) => {
+                return PreparedFile::Skipped(
+                    "File type is unknown: its contents are not a known photo or video format.",
+                )
             }
+            Ok(None) => return PreparedFile::Skipped("File is not a photo or video."),
+            Err(err) if is_photo_extension(&extension) => return PreparedFile::Photo(Err(err)),
+            // a file that is not named like a photo (e.g. a locked Thumbs.db) is not an error
+            Err(_) => {
+                return PreparedFile::Skipped("File could not be read to recognize its type.")
+            }
+        }
+    };
+
+    match file_type {
+        Some(file_type) => {
+            PreparedFile::Photo(prepare_photo(file_path, file_type, options).map(Box::new))
         }
+        None => PreparedFile::Sidecar(prepare_sidecar(file_path)),
     }
+}
 
-    false
+#[doc(hidden)]
+fn prepare_sidecar(file_path: &Path) -> anyhow::Result<PreparedSidecar> {
+    Ok(PreparedSidecar {
+        hash: get_file_hash(file_path)?,
+        len: fs::metadata(file_path)?.len(),
+    })
 }
 
 #[doc(hidden)]
-fn get_organized_photo_path(
+fn prepare_photo(
     file_path: &Path,
-    date_time: &chrono::DateTime<Utc>,
-    conflict: u32,
-    organized_dir: &Path,
-) -> anyhow::Result<PathBuf> {
-    // folder path is '(organized)year/month' (i.e. YYYY/MM)
-    let mut dest_path = PathBuf::from(organized_dir);
-    dest_path.push(PathBuf::from(date_time.format("%Y").to_string()).as_path());
-    dest_path.push(PathBuf::from(date_time.format("%m").to_string()).as_path());
+    file_type: FileType,
+    options: &OrganizeOptions,
+) -> anyhow::Result<PreparedPhoto> {
+    let (photo_info, correction) = load_corrected_photo_info(file_path, file_type, options)?;
 
-    // file name is 'year-month-day hour-minute-second-fractionOfSeconds conflict' (i.e. YYYY-MM-DD HH-MM-SS-FFFFFFFFF CCC)
-    let mut file_name = date_time.format("%Y-%m-%d %H-%M-%S-%f").to_string();
+    prepare_loaded_photo(file_path, file_type, &photo_info, correction, options)
+}
 
-    // add .jpg to the end so that set_extension doesn't overwrite the last part
-    if conflict > 0 {
-        file_name = format!("{} {:03}.jpg", file_name, conflict);
-    } else {
-        file_name = format!("{}.jpg", file_name);
-    }
+/// Loads the information for a photo and corrects its camera clock, returning the correction that was applied.
+#[doc(hidden)]
+fn load_corrected_photo_info(
+    file_path: &Path,
+    file_type: FileType,
+    options: &OrganizeOptions,
+) -> anyhow::Result<(PhotoInfo, Option<ClockCorrection>)> {
+    let mut photo_info = PhotoInfo::load(file_path, file_type, &options.dates)?;
+
+    // correct the camera clock before the date-time is used for the path
+    // the correction is matched against the camera clock as it was, before implausible date-times are left out
+    let correction = match photo_info.date_time_info.camera_date_time(&options.dates) {
+        Some(date_time) => {
+            find_clock_correction(&options.clock_corrections, &photo_info.camera, &date_time)
+                .filter(|correction| photo_info.date_time_info.correct_clock(correction.shift))
+                .cloned()
+        }
+        None => None,
+    };
+
+    Ok((photo_info, correction))
+}
+
+/// Reads the rest of the information needed to organize a photo whose information is loaded.
+#[doc(hidden)]
+fn prepare_loaded_photo(
+    file_path: &Path,
+    file_type: FileType,
+    photo_info: &PhotoInfo,
+    correction: Option<ClockCorrection>,
+    options: &OrganizeOptions,
+) -> anyhow::Result<PreparedPhoto> {
+    Ok(PreparedPhoto {
+        date: photo_info
+            .date_time_info
+            .best(&options.dates)
+            .map(|(date_time, date_source)| (date_time.naive(options.time_zone), date_source)),
+        correction,
+        file_type,
+        extension: placed_extension(file_path, file_type, options.fix_extensions),
+        camera: photo_info.camera.clone(),
+        hash: get_file_hash(file_path)?,
+        len: fs::metadata(file_path)?.len(),
+    })
+}
+
+/// Groups the files of a directory with their companions and sidecars, in the order of the first file of each group.
+///
+/// Companions are photos with the same name but a different extension (ignoring case), such as the RAW and JPEG
+/// files of a RAW+JPEG pair or the photo and video of a Live Photo. Sidecars are named after the name of their photo
+/// (e.g. `IMG_0001.xmp`), its whole file name (e.g. `IMG_0001.jpg.xmp`), or are its Google Takeout JSON file (e.g.
+/// `IMG_0001.jpg.json`). Other files are not grouped.
+#[doc(hidden)]
+fn group_companions(files: &[&PathBuf], prepared: &[PreparedFile]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut groups_by_stem: HashMap<String, usize> = HashMap::new();
+    let mut groups_by_name: HashMap<String, usize> = HashMap::new();
+    let mut groups_by_takeout_name: HashMap<String, usize> = HashMap::new();
+    let mut sidecars = Vec::new();
+
+    for (index, file) in files.iter().enumerate() {
+        let stem = lowercase_stem(file);
+        let name = file.file_name().unwrap().to_str().unwrap().to_lowercase();
+
+        let group = match &prepared[index] {
+            // a photo that could not be read is grouped with its companions and sidecars, which are left with it
+            PreparedFile::Photo(result) => {
+                let extension = result.as_ref().ok().map(|photo| &photo.extension);
+
+                // a second file placed with the same name and extension (e.g. the same extension in a different case,
+                // or a fixed extension) is not a companion
+                match groups_by_stem.get(&stem) {
+                    Some(&group)
+                        if extension.is_none()
+                            || !groups[group].iter().any(|&member| {
+                                placed_extension_of(&prepared[member]) == extension
+                            }) =>
+                    {
+                        groups[group].push(index);
+                        group
+                    }
+                    _ => {
+                        groups_by_stem.insert(stem, groups.len());
+                        groups.push(vec![index]);
+                        groups.len() - 1
+                    }
+                }
+            }
+            PreparedFile::Sidecar(_) => {
+                sidecars.push(index);
+                continue;
+            }
+            PreparedFile::Skipped(_) => {
+                groups.push(vec![index]);
+                continue;
+            }
+        };
 
-    dest_path.push(PathBuf::from(file_name).as_path());
+        // the JSON file shared with an edited copy stays with the original
+        for takeout_name in takeout_json_names(&name) {
+            if is_edited_photo_name(&name) {
+                groups_by_takeout_name.entry(takeout_name).or_insert(group);
+            } else {
+                groups_by_takeout_name.insert(takeout_name, group);
+            }
+        }
+        groups_by_name.insert(name, group);
+    }
 
-    // extension is maintained, but made lowercase for consistency
-    let extension = OsString::from(
-        file_path
-            .extension()
+    for index in sidecars {
+        let name = files[index]
+            .file_name()
             .unwrap()
             .to_str()
             .unwrap()
-            .to_lowercase(),
-    );
+            .to_lowercase();
+        let stem = lowercase_stem(files[index]);
+        let group = groups_by_takeout_name
+            .get(&name)
+            .or_else(|| groups_by_name.get(&stem))
+            .or_else(|| groups_by_stem.get(&stem));
+
+        match group {
+            Some(&group) => groups[group].push(index),
+            None => groups.push(vec![index]),
+        }
+    }
+
+    groups.sort_by_key(|group| group.iter().min().copied());
+    groups
+}
+
+/// The photo in a group that a sidecar is named after: the photo with its whole file name (or whose Takeout JSON file
+/// it is), otherwise the primary photo when it has the same name. None if the sidecar's photo is not in the group.
+#[doc(hidden)]
+fn sidecar_photo_index(
+    sidecar_path: &Path,
+    photos: &[(&Path, Box<PreparedPhoto>)],
+) -> Option<usize> {
+    let stem = lowercase_stem(sidecar_path);
+
+    let by_name = photos
+        .iter()
+        .position(|(file_path, _)| is_full_name_sidecar(sidecar_path, file_path));
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    if photos.is_empty() || lowercase_stem(photos[0].0) != stem {
+        return None;
+    }
+
+    Some(primary_photo_index(photos))
+}
 
-    dest_path.set_extension(extension);
+/// The primary photo of a group is the first that is not a video (e.g. the photo of a Live Photo).
+#[doc(hidden)]
+fn primary_photo_index(photos: &[(&Path, Box<PreparedPhoto>)]) -> usize {
+    photos
+        .iter()
+        .position(|(_, photo)| !photo.file_type.is_video)
+        .unwrap_or(0)
+}
+
+/// Determines if a file is a sidecar by inspecting the extension
+#[doc(hidden)]
+fn is_sidecar_file(path: &Path, sidecar_extensions: &[String]) -> bool {
+    let extension = lowercase_extension(path);
+    !extension.is_empty() && sidecar_extensions.contains(&extension)
+}
+
+/// The name of the file without its extension, made lowercase for comparing
+#[doc(hidden)]
+fn lowercase_stem(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The extension of the file, made lowercase for consistency
+#[doc(hidden)]
+fn lowercase_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The extension a prepared photo is placed with, or None if the file is not a photo that was read.
+#[doc(hidden)]
+fn placed_extension_of(prepared: &PreparedFile) -> Option<&String> {
+    match prepared {
+        PreparedFile::Photo(Ok(photo)) => Some(&photo.extension),
+        _ => None,
+    }
+}
+
+/// The extension a photo is placed with: its own extension in lower case, or the usual extension of its format when
+/// it has none or when fixing extensions and its own does not match its format.
+///
+/// A TIFF file is never given a fixed extension, since a RAW file that is a plain TIFF file (e.g. `.pef`) cannot be
+/// told apart from a TIFF file, and its extension is the only hint of its format.
+#[doc(hidden)]
+fn placed_extension(file_path: &Path, file_type: FileType, fix_extensions: bool) -> String {
+    let extension = lowercase_extension(file_path);
+    let fix = fix_extensions && file_type != TIFF && !file_type.has_extension(&extension);
+    if extension.is_empty() || fix {
+        return file_type.extension().to_string();
+    }
 
-    return Ok(dest_path);
+    extension
+}
+
+/// Renders the organized path of a file in a group, which is named after the primary photo of the group but keeps its
+/// own extension.
+#[doc(hidden)]
+fn get_organized_photo_path(
+    primary_path: &Path,
+    extension: &str,
+    primary: &PreparedPhoto,
+    conflict: u32,
+    organized_dir: &Path,
+    template: &PathTemplate,
+) -> anyhow::Result<PathBuf> {
+    get_template_photo_path(
+        primary_path,
+        extension,
+        primary,
+        None,
+        conflict,
+        organized_dir,
+        template,
+    )
 }
 
 #[doc(hidden)]
 fn get_duplicate_photo_path(
     file_path: &Path,
-    date_time: &chrono::DateTime<Utc>,
-    hash: &str,
+    photo: &PreparedPhoto,
+    matched_path: &Path,
     conflict: u32,
     duplicates_dir: &Path,
+    template: &PathTemplate,
 ) -> anyhow::Result<PathBuf> {
-    // folder path is (duplicates)year/month (i.e. YYYY/MM)
-    let mut dest_path = PathBuf::from(duplicates_dir);
-    dest_path.push(PathBuf::from(date_time.format("%Y").to_string()).as_path());
-    dest_path.push(PathBuf::from(date_time.format("%m").to_string()).as_path());
-
-    // file name is 'hash conflict'
-    let mut file_name = format!("{}", hash);
+    let matched = matched_path.file_stem().unwrap().to_str().unwrap();
+
+    get_template_photo_path(
+        file_path,
+        &photo.extension,
+        photo,
+        Some(matched),
+        conflict,
+        duplicates_dir,
+        template,
+    )
+}
 
-    // add .jpg to the end so that set_extension doesn't overwrite the last part
-    if conflict > 0 {
-        file_name = format!("{}.{:03}.jpg", file_name, conflict);
-    } else {
-        file_name = format!("{}.jpg", file_name);
+/// The path of an undated photo in the undated directory, which is its path relative to the unorganized directory.
+/// The name is kept as it is, with ` CCC` added before the extension when there is a conflict, and the extension the
+/// photo is placed with when it is not its own.
+#[doc(hidden)]
+fn get_undated_photo_path(
+    file_path: &Path,
+    photo: &PreparedPhoto,
+    conflict: u32,
+    unorganized_dir: &Path,
+    undated_dir: &Path,
+) -> PathBuf {
+    let relative_path = file_path.strip_prefix(unorganized_dir).unwrap_or(file_path);
+    let dest_path = undated_dir.join(relative_path);
+    let own_extension = photo.extension == lowercase_extension(file_path);
+    if conflict == 0 && own_extension {
+        return dest_path;
     }
 
-    dest_path.push(PathBuf::from(file_name).as_path());
+    let stem = file_path.file_stem().unwrap().to_str().unwrap();
+    let extension = if own_extension {
+        file_path.extension().and_then(|ext| ext.to_str())
+    } else {
+        Some(photo.extension.as_str())
+    };
+    let conflict = if conflict == 0 {
+        String::new()
+    } else {
+        format!(" {:03}", conflict)
+    };
+    let name = match extension {
+        Some(ext) => format!("{}{}.{}", stem, conflict, ext),
+        None => format!("{}{}", stem, conflict),
+    };
+
+    dest_path.with_file_name(name)
+}
 
-    // extension is maintained, but made lowercase for consistency
-    let extension = OsString::from(
-        file_path
-            .extension()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_lowercase(),
-    );
-    dest_path.set_extension(extension);
+/// Determines if a sidecar is named after the whole file name of its photo (e.g. `IMG_0001.jpg.xmp`), or is its
+/// Google Takeout JSON file (e.g. `IMG_0001.jpg.json` or `IMG_0001.jpg.supplemental-metadata.json`).
+#[doc(hidden)]
+fn is_full_name_sidecar(sidecar_path: &Path, photo_path: &Path) -> bool {
+    let photo_name = photo_path.file_name().unwrap().to_str().unwrap();
+    let sidecar_name = sidecar_path.file_name().unwrap().to_str().unwrap();
 
-    return Ok(dest_path);
+    lowercase_stem(sidecar_path) == photo_name.to_lowercase()
+        || is_takeout_json_name(sidecar_name, photo_name)
 }
 
-/// Returns true if the files are the same length and the file hases are equal
+/// The path of a sidecar next to where its photo was placed. A sidecar named after the whole file name of its photo
+/// (e.g. `IMG_0001.jpg.json`) is named after the whole placed file name; otherwise after the placed name.
 #[doc(hidden)]
-fn are_same_file_contents(x: &Path, y: &Path) -> anyhow::Result<Option<String>> {
-    let x_len = fs::metadata(x)?.len();
-    let y_len = fs::metadata(y)?.len();
-
-    if x_len != y_len {
-        return Ok(None);
-    }
+fn get_sidecar_path(sidecar_path: &Path, photo_path: &Path, placed_path: &Path) -> PathBuf {
+    let placed_name = if is_full_name_sidecar(sidecar_path, photo_path) {
+        placed_path.file_name().unwrap()
+    } else {
+        placed_path.file_stem().unwrap()
+    };
+
+    placed_path.with_file_name(format!(
+        "{}.{}",
+        placed_name.to_str().unwrap(),
+        lowercase_extension(sidecar_path)
+    ))
+}
 
-    let x_hash = get_file_hash(x)?;
-    let y_hash = get_file_hash(y)?;
+/// Renders the template for a photo, relative to the directory. The name comes from `name_path`.
+#[doc(hidden)]
+fn get_template_photo_path(
+    name_path: &Path,
+    extension: &str,
+    photo: &PreparedPhoto,
+    matched: Option<&str>,
+    conflict: u32,
+    dir: &Path,
+    template: &PathTemplate,
+) -> anyhow::Result<PathBuf> {
+    let (date_time, _) = photo
+        .date
+        .context("The photo does not have a date-time for its path.")?;
+
+    let values = TemplateValues {
+        date_time,
+        stem: name_path.file_stem().unwrap().to_str().unwrap(),
+        ext: extension,
+        make: photo.camera.make.as_deref(),
+        model: photo.camera.model.as_deref(),
+        lens: photo.camera.lens.as_deref(),
+        hash: &photo.hash,
+        matched,
+        conflict,
+    };
+
+    Ok(dir.join(template.render(&values)))
+}
 
-    if x_hash != y_hash {
-        return Ok(None);
+/// Returns true if the file has the given length and the file hashes are equal
+#[doc(hidden)]
+fn is_same_file_contents(file_path: &Path, hash: &str, len: u64) -> anyhow::Result<bool> {
+    if fs::metadata(file_path)?.len() != len {
+        return Ok(false);
     }
 
-    return Ok(Some(x_hash));
+    Ok(get_file_hash(file_path)? == hash)
 }
 
+/// Canonicalizes a directory. A directory that does not exist yet (i.e. during a dry run, or an undated directory that
+/// is only created when it is needed) is canonicalized through its parent instead.
 #[doc(hidden)]
-fn move_file(from: &Path, to: &Path) -> io::Result<()> {
-    if let Some(to_dir) = to.parent() {
-        fs::create_dir_all(to_dir)?;
+fn canonicalize_dir(dir: &Path) -> io::Result<PathBuf> {
+    if dir.exists() {
+        return fs::canonicalize(dir);
     }
 
-    fs::rename(from, to)?;
-
-    Ok(())
+    match (dir.parent(), dir.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            Ok(canonicalize_dir(parent)?.join(name))
+        }
+        _ => Ok(env::current_dir()?.join(dir)),
+    }
 }
 
 /// The reverse of fs::canonicalize.  Returns the path with the lay base instead of the the cannonical base.
@@ -591,6 +1853,60 @@ fn decry_path(canonical_path: &Path, canonical_base: &Path, lay_base: &Path) ->
 }
 
 #[doc(hidden)]
-fn increment(cell: &Cell<u64>) {
-    cell.set(cell.get() + 1)
+fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placed_extension_is_lower_case() {
+        assert_eq!(
+            placed_extension(Path::new("IMG_0001.JPG"), JPEG, false),
+            "jpg"
+        );
+        assert_eq!(
+            placed_extension(Path::new("IMG_0001.JPEG"), JPEG, true),
+            "jpeg"
+        );
+    }
+
+    #[test]
+    fn placed_extension_is_added_when_missing() {
+        assert_eq!(placed_extension(Path::new("IMG_0001"), JPEG, false), "jpg");
+        assert_eq!(placed_extension(Path::new("IMG_0001"), TIFF, true), "tif");
+    }
+
+    #[test]
+    fn placed_extension_is_only_fixed_when_fixing_extensions() {
+        assert_eq!(placed_extension(Path::new("shot.jpg"), PNG, false), "jpg");
+        assert_eq!(placed_extension(Path::new("shot.jpg"), PNG, true), "png");
+        assert_eq!(
+            placed_extension(Path::new("IMG_0001.jpg_original"), JPEG, true),
+            "jpg"
+        );
+    }
+
+    #[test]
+    fn placed_extension_of_tiff_is_never_fixed() {
+        for name in ["IMGP0001.PEF", "SAM_0001.srw", "DSC_0001.nrw", "scan.png"].iter() {
+            let extension = lowercase_extension(Path::new(name));
+            assert_eq!(
+                placed_extension(Path::new(name), TIFF, true),
+                extension,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn placed_extension_of_3g2_is_kept() {
+        assert_eq!(
+            placed_extension(Path::new("VID_0001.3g2"), THREE_GP, true),
+            "3g2"
+        );
+    }
 }
@@ -0,0 +1,368 @@
+use crate::bmff::*;
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// The header of a Fujifilm RAF file.
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+
+/// The uuid box in a Canon CR3 file that contains the CMT (i.e. TIFF) boxes.
+const CR3_CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// The TIFF tags that point to the EXIF and GPS IFDs.
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const GPS_IFD_POINTER: u16 = 0x8825;
+const INTEROP_IFD_POINTER: u16 = 0xa005;
+
+/// The most IFDs read from a TIFF file, which has IFD0, its thumbnail IFD, and the EXIF, GPS, and interoperability
+/// IFDs.
+const MAX_TIFF_IFDS: usize = 16;
+
+/// The Panasonic tag that contains a JPEG preview with the full EXIF information.
+const RW2_JPG_FROM_RAW: u16 = 0x002e;
+
+/// Reads the EXIF information of a camera RAW file that is not a plain TIFF file, or None if it is not one of these
+/// RAW formats.
+///
+/// Plain TIFF based RAW formats (i.e. CR2, NEF, ARW, DNG) are read with the other photos. This handles:
+/// * ORF and RW2, which are TIFF files with a different magic number.
+/// * RAF, which contains a JPEG with the EXIF information.
+/// * CR3, which is an ISO base media file with the TIFF IFDs in separate boxes.
+pub fn read_raw_exif(file_path: &Path) -> anyhow::Result<Option<exif::Exif>> {
+    let mut reader = BufReader::new(File::open(file_path)?);
+
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header)?;
+
+    match &header[0..4] {
+        // Olympus ORF
+        b"IIRO" | b"IIRS" | b"MMOR" => read_patched_tiff(&mut reader),
+        // Panasonic RW2
+        b"IIU\0" => read_rw2(&mut reader),
+        _ if header.starts_with(RAF_MAGIC) => read_raf(&mut reader),
+        _ if &header[4..8] == b"ftyp" && &header[8..12] == b"crx " => read_cr3(&mut reader),
+        _ => Ok(None),
+    }
+}
+
+/// Reads a TIFF file whose magic number is not 42, by replacing its magic number.
+///
+/// Only the header, the IFDs, and the values they point to are read, into a buffer that is otherwise zeros, so the
+/// image data of the RAW file is not read.
+#[doc(hidden)]
+fn read_patched_tiff(reader: &mut BufReader<File>) -> anyhow::Result<Option<exif::Exif>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let header = read_at(reader, 0, 8)?;
+    let endian = match TiffEndian::new(&header) {
+        Some(endian) => endian,
+        None => return Ok(None),
+    };
+    let ifd0 = match endian.u32(&header, 4) {
+        Some(ifd0) => ifd0 as u64,
+        None => return Ok(None),
+    };
+
+    let mut ranges = vec![(0, header)];
+    let mut ifds = vec![ifd0];
+    let mut visited = Vec::new();
+    while let Some(ifd) = ifds.pop() {
+        // a corrupt file may point back to an IFD that was already read
+        if ifd == 0 || visited.contains(&ifd) || visited.len() >= MAX_TIFF_IFDS || ifd + 2 > len {
+            continue;
+        }
+        visited.push(ifd);
+
+        let count = match endian.u16(&read_at(reader, ifd, 2)?, 0) {
+            Some(count) => count as u64,
+            None => continue,
+        };
+        let table = read_at(reader, ifd, (2 + count * 12 + 4).min(len - ifd))?;
+        for index in 0..count as usize {
+            let entry = 2 + index * 12;
+            let (tag, field_type, count, value) = match (
+                endian.u16(&table, entry),
+                endian.u16(&table, entry + 2),
+                endian.u32(&table, entry + 4),
+                endian.u32(&table, entry + 8),
+            ) {
+                (Some(tag), Some(field_type), Some(count), Some(value)) => {
+                    (tag, field_type, count, value as u64)
+                }
+                _ => break,
+            };
+
+            if tag == EXIF_IFD_POINTER || tag == GPS_IFD_POINTER || tag == INTEROP_IFD_POINTER {
+                ifds.push(value);
+            }
+
+            // values of up to 4 bytes are in the entry itself
+            let size = tiff_type_size(field_type) as u64 * count as u64;
+            if size > 4 && matches!(value.checked_add(size), Some(end) if end <= len) {
+                ranges.push((value, read_at(reader, value, size)?));
+            }
+        }
+        if let Some(next_ifd) = endian.u32(&table, 2 + count as usize * 12) {
+            ifds.push(next_ifd as u64);
+        }
+        ranges.push((ifd, table));
+    }
+
+    let data_len = ranges
+        .iter()
+        .map(|(offset, bytes)| *offset as usize + bytes.len())
+        .max()
+        .unwrap_or_default();
+    let mut data = vec![0u8; data_len];
+    for (offset, bytes) in &ranges {
+        data[*offset as usize..*offset as usize + bytes.len()].copy_from_slice(bytes);
+    }
+
+    let magic: [u8; 2] = if data[0] == b'I' { [42, 0] } else { [0, 42] };
+    data[2..4].copy_from_slice(&magic);
+
+    Ok(exif::Reader::new().read_raw(data).ok())
+}
+
+/// Reads part of a file.
+#[doc(hidden)]
+fn read_at(reader: &mut BufReader<File>, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// RW2 files have the date-times in the TIFF IFDs, or in the JPEG preview when they are not.
+#[doc(hidden)]
+fn read_rw2(reader: &mut BufReader<File>) -> anyhow::Result<Option<exif::Exif>> {
+    let exif = match read_patched_tiff(reader)? {
+        Some(exif) => exif,
+        None => return Ok(None),
+    };
+
+    if exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .is_some()
+    {
+        return Ok(Some(exif));
+    }
+
+    let preview = exif
+        .fields()
+        .find(|field| field.tag.number() == RW2_JPG_FROM_RAW && field.ifd_num == exif::In::PRIMARY);
+    if let Some(exif::Value::Undefined(jpeg, _)) = preview.map(|field| &field.value) {
+        if let Ok(preview_exif) = exif::Reader::new().read_from_container(&mut Cursor::new(jpeg)) {
+            return Ok(Some(preview_exif));
+        }
+    }
+
+    Ok(Some(exif))
+}
+
+/// RAF files have a JPEG preview with the EXIF information. Its offset and length are at 84 and 88.
+#[doc(hidden)]
+fn read_raf(reader: &mut BufReader<File>) -> anyhow::Result<Option<exif::Exif>> {
+    let mut pointers = [0u8; 8];
+    reader.seek(SeekFrom::Start(84))?;
+    reader.read_exact(&mut pointers)?;
+
+    let offset = u32::from_be_bytes([pointers[0], pointers[1], pointers[2], pointers[3]]) as u64;
+    let len = u32::from_be_bytes([pointers[4], pointers[5], pointers[6], pointers[7]]) as u64;
+
+    let mut jpeg = Vec::new();
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.take(len).read_to_end(&mut jpeg)?;
+
+    Ok(exif::Reader::new()
+        .read_from_container(&mut Cursor::new(jpeg))
+        .ok())
+}
+
+/// CR3 files have IFD0 in `moov/uuid/CMT1` and the EXIF IFD in `moov/uuid/CMT2`, each as its own TIFF file.
+/// They are merged into one TIFF file so the EXIF IFD is read as the EXIF IFD.
+#[doc(hidden)]
+fn read_cr3(reader: &mut BufReader<File>) -> anyhow::Result<Option<exif::Exif>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+
+    let moov = match find_box(reader, 0, len, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(None),
+    };
+
+    let mut canon = None;
+    for uuid in read_boxes(reader, moov.offset, moov.end())?
+        .into_iter()
+        .filter(|b| &b.box_type == b"uuid" && b.size >= 16)
+    {
+        if read_box_contents(reader, &uuid, 16)? == CR3_CANON_UUID {
+            canon = Some(BmffBox {
+                box_type: uuid.box_type,
+                offset: uuid.offset + 16,
+                size: uuid.size - 16,
+            });
+            break;
+        }
+    }
+
+    let canon = match canon {
+        Some(canon) => canon,
+        None => return Ok(None),
+    };
+
+    let cmt1 = match find_box(reader, canon.offset, canon.end(), b"CMT1")? {
+        Some(cmt1) => read_box_contents(reader, &cmt1, cmt1.size)?,
+        None => return Ok(None),
+    };
+
+    let tiff = match find_box(reader, canon.offset, canon.end(), b"CMT2")? {
+        Some(cmt2) => merge_exif_ifd(&cmt1, &read_box_contents(reader, &cmt2, cmt2.size)?),
+        None => Some(cmt1),
+    };
+
+    Ok(tiff.and_then(|tiff| exif::Reader::new().read_raw(tiff).ok()))
+}
+
+// -------------------- TIFF merging -------------------- //
+
+/// Reads the numbers of a TIFF file in its byte order.
+#[doc(hidden)]
+struct TiffEndian {
+    little: bool,
+}
+
+#[doc(hidden)]
+impl TiffEndian {
+    fn new(tiff: &[u8]) -> Option<TiffEndian> {
+        match tiff.get(0..2)? {
+            b"II" => Some(TiffEndian { little: true }),
+            b"MM" => Some(TiffEndian { little: false }),
+            _ => None,
+        }
+    }
+
+    fn u16(&self, tiff: &[u8], offset: usize) -> Option<u16> {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if self.little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, tiff: &[u8], offset: usize) -> Option<u32> {
+        let bytes = [
+            *tiff.get(offset)?,
+            *tiff.get(offset + 1)?,
+            *tiff.get(offset + 2)?,
+            *tiff.get(offset + 3)?,
+        ];
+        Some(if self.little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.little {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        if self.little {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+}
+
+/// The size in bytes of a TIFF field type.
+#[doc(hidden)]
+fn tiff_type_size(field_type: u16) -> u32 {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+/// Merges two TIFF files: the IFD0 of `ifd0_tiff` and the IFD0 of `exif_tiff` as its EXIF IFD.
+///
+/// `exif_tiff` is appended to `ifd0_tiff` and its offsets are moved by the length of `ifd0_tiff`. A new IFD0 is then
+/// appended with the fields of the original IFD0 and a pointer to the EXIF IFD. Returns None if either file is corrupt,
+/// including an offset that overflows once it is moved.
+#[doc(hidden)]
+fn merge_exif_ifd(ifd0_tiff: &[u8], exif_tiff: &[u8]) -> Option<Vec<u8>> {
+    let endian = TiffEndian::new(ifd0_tiff)?;
+    if TiffEndian::new(exif_tiff)?.little != endian.little {
+        return None;
+    }
+
+    let base = ifd0_tiff.len() as u32;
+    let mut merged = ifd0_tiff.to_vec();
+    merged.extend_from_slice(exif_tiff);
+
+    // move the offsets of the EXIF IFD
+    let exif_ifd = base.checked_add(endian.u32(exif_tiff, 4)?)?;
+    let exif_ifd_start = exif_ifd as usize;
+    let exif_count = endian.u16(&merged, exif_ifd_start)? as usize;
+    for index in 0..exif_count {
+        let entry = exif_ifd_start + 2 + index * 12;
+        let tag = endian.u16(&merged, entry)?;
+        let field_type = endian.u16(&merged, entry + 2)?;
+        let count = endian.u32(&merged, entry + 4)?;
+        let value = endian.u32(&merged, entry + 8)?;
+
+        if tag == INTEROP_IFD_POINTER || tiff_type_size(field_type).saturating_mul(count) > 4 {
+            let moved = value.checked_add(base)?;
+            merged[entry + 8..entry + 12].copy_from_slice(&endian.u32_bytes(moved));
+        }
+    }
+    let next_ifd = exif_ifd_start + 2 + exif_count * 12;
+    merged
+        .get_mut(next_ifd..next_ifd + 4)?
+        .copy_from_slice(&[0; 4]);
+
+    // copy the entries of IFD0, without its (invalid) pointers to other IFDs
+    let ifd0 = endian.u32(ifd0_tiff, 4)? as usize;
+    let ifd0_count = endian.u16(ifd0_tiff, ifd0)? as usize;
+    let mut entries: Vec<(u16, Vec<u8>)> = Vec::new();
+    for index in 0..ifd0_count {
+        let entry = ifd0 + 2 + index * 12;
+        let tag = endian.u16(ifd0_tiff, entry)?;
+        if tag != EXIF_IFD_POINTER && tag != GPS_IFD_POINTER {
+            entries.push((tag, ifd0_tiff.get(entry..entry + 12)?.to_vec()));
+        }
+    }
+
+    let mut pointer = Vec::with_capacity(12);
+    pointer.extend_from_slice(&endian.u16_bytes(EXIF_IFD_POINTER));
+    pointer.extend_from_slice(&endian.u16_bytes(4));
+    pointer.extend_from_slice(&endian.u32_bytes(1));
+    pointer.extend_from_slice(&endian.u32_bytes(exif_ifd));
+    entries.push((EXIF_IFD_POINTER, pointer));
+    entries.sort_by_key(|(tag, _)| *tag);
+
+    // append the new IFD0 (on a word boundary) and point the header to it
+    if merged.len() % 2 == 1 {
+        merged.push(0);
+    }
+    let new_ifd0 = merged.len() as u32;
+    merged.extend_from_slice(&endian.u16_bytes(entries.len() as u16));
+    for (_, entry) in &entries {
+        merged.extend_from_slice(entry);
+    }
+    merged.extend_from_slice(&[0; 4]);
+    merged[4..8].copy_from_slice(&endian.u32_bytes(new_ifd0));
+
+    Some(merged)
+}
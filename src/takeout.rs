@@ -0,0 +1,242 @@
+use crate::photo_date_time::*;
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The longest name Google Takeout gives a JSON file, before its `(n)` counter and `.json` extension.
+const MAX_TAKEOUT_NAME_LEN: usize = 46;
+
+/// Newer Takeout exports name the JSON file `<name>.supplemental-metadata.json` (truncated like any other name).
+const SUPPLEMENTAL_METADATA: &str = ".supplemental-metadata";
+
+/// The suffixes Google Photos adds to the names of edited photos, which share the JSON file of the original.
+const EDITED_SUFFIXES: &[&str] = &[
+    "-edited",
+    "-bearbeitet",
+    "-modifié",
+    "-editado",
+    "-modificato",
+];
+
+/// Reads the date-time a photo was taken from its Google Takeout JSON file (`photoTakenTime.timestamp`), or None if
+/// the photo does not have a JSON file or it does not have a date-time.
+pub fn read_takeout_date_time(file_path: &Path) -> anyhow::Result<Option<PhotoDateTime>> {
+    let json = match find_takeout_json(file_path) {
+        Some(json_path) => read_takeout_json(&json_path)?,
+        None => return Ok(None),
+    };
+
+    Ok(parse_photo_taken_time(&json))
+}
+
+/// Reads the path of a photo's Google Takeout JSON file, followed by the raw value of its
+/// `photoTakenTime.timestamp`.
+pub fn read_takeout_raw_values(file_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let json_path = match find_takeout_json(file_path) {
+        Some(json_path) => json_path,
+        None => return Ok(Vec::new()),
+    };
+
+    let json = read_takeout_json(&json_path)?;
+    let mut values = vec![("TakeoutJson".to_string(), json_path.display().to_string())];
+    let timestamp = match &json["photoTakenTime"]["timestamp"] {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        timestamp => Some(timestamp.to_string()),
+    };
+    if let Some(timestamp) = timestamp {
+        values.push(("photoTakenTime.timestamp".to_string(), timestamp));
+    }
+    Ok(values)
+}
+
+/// Determines if a photo is an edited copy (e.g. `IMG_0001-edited.jpg`), which shares the JSON file of the original.
+pub fn is_edited_photo_name(file_name: &str) -> bool {
+    let stem = match file_name.rfind('.') {
+        Some(index) if index > 0 => &file_name[..index],
+        _ => file_name,
+    };
+
+    let (stem, _) = split_counter(stem);
+    EDITED_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+}
+
+/// Determines if a file is the Google Takeout JSON file of a photo, ignoring case.
+pub fn is_takeout_json_name(json_name: &str, file_name: &str) -> bool {
+    let json_name = json_name.to_lowercase();
+    takeout_json_names(file_name)
+        .iter()
+        .any(|name| name.to_lowercase() == json_name)
+}
+
+/// The names Google Takeout may have given the JSON file of a photo, most likely first.
+///
+/// Takeout names the JSON file after the whole file name of the photo (e.g. `IMG_0001.jpg.json`), but:
+/// * long names are truncated to 46 characters before `.json`.
+/// * the counter of a photo with the same name as another moves to the end (e.g. `IMG_0001(1).jpg` has
+///   `IMG_0001.jpg(1).json`).
+/// * an edited photo (e.g. `IMG_0001-edited.jpg`) shares the JSON file of the original.
+/// * newer exports add `.supplemental-metadata` before `.json`, and older exports may drop the photo's extension.
+pub fn takeout_json_names(file_name: &str) -> Vec<String> {
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(index) if index > 0 => (&file_name[..index], Some(&file_name[index..])),
+        _ => (file_name, None),
+    };
+
+    let (stem, counter) = split_counter(stem);
+    let stem = EDITED_SUFFIXES
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix))
+        .unwrap_or(stem);
+    let name = format!("{}{}", stem, extension.unwrap_or_default());
+
+    let mut names = Vec::new();
+    for base in [
+        name.clone(),
+        format!("{}{}", name, SUPPLEMENTAL_METADATA),
+        stem.to_string(),
+    ]
+    .iter()
+    {
+        for base in [base.clone(), truncate(base, MAX_TAKEOUT_NAME_LEN)].iter() {
+            let json_name = format!("{}{}.json", base, counter);
+            if !names.contains(&json_name) {
+                names.push(json_name);
+            }
+        }
+    }
+
+    names
+}
+
+/// Splits the counter (e.g. `(1)`) from the end of a stem.
+#[doc(hidden)]
+fn split_counter(stem: &str) -> (&str, &str) {
+    if let Some(without_paren) = stem.strip_suffix(')') {
+        if let Some(index) = without_paren.rfind('(') {
+            let digits = &without_paren[index + 1..];
+            if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+                return (&stem[..index], &stem[index..]);
+            }
+        }
+    }
+
+    (stem, "")
+}
+
+/// Truncates text to a number of characters.
+#[doc(hidden)]
+fn truncate(text: &str, len: usize) -> String {
+    text.chars().take(len).collect()
+}
+
+/// Finds the Google Takeout JSON file of a photo, by each of the names Takeout may have given it.
+#[doc(hidden)]
+fn find_takeout_json(file_path: &Path) -> Option<PathBuf> {
+    let dir = file_path.parent()?;
+    let file_name = file_path.file_name()?.to_string_lossy();
+
+    takeout_json_names(&file_name)
+        .into_iter()
+        .map(|json_name| dir.join(json_name))
+        .find(|json_path| json_path.is_file())
+}
+
+#[doc(hidden)]
+fn read_takeout_json(json_path: &Path) -> anyhow::Result<Value> {
+    Ok(serde_json::from_str(&fs::read_to_string(json_path)?)?)
+}
+
+/// The timestamp is the number of seconds since 1970-01-01 in UTC, as a string (or a number in some exports).
+/// A timestamp of 0 means it was not set.
+#[doc(hidden)]
+fn parse_photo_taken_time(json: &Value) -> Option<PhotoDateTime> {
+    let timestamp = &json["photoTakenTime"]["timestamp"];
+    let seconds = match timestamp {
+        Value::String(text) => text.trim().parse::<i64>().ok()?,
+        Value::Number(number) => number.as_i64()?,
+        _ => return None,
+    };
+
+    if seconds <= 0 {
+        return None;
+    }
+
+    NaiveDateTime::from_timestamp_opt(seconds, 0).map(|utc| PhotoDateTime::from_naive_utc(&utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_has_name(file_name: &str, json_name: &str) {
+        let names = takeout_json_names(file_name);
+        assert!(
+            names.iter().any(|name| name == json_name),
+            "{} is not a JSON name of {}: {:?}",
+            json_name,
+            file_name,
+            names
+        );
+    }
+
+    #[test]
+    fn json_name_is_the_whole_file_name_first() {
+        let names = takeout_json_names("IMG_0001.jpg");
+        assert_eq!(names[0], "IMG_0001.jpg.json");
+        assert_has_name("IMG_0001.jpg", "IMG_0001.json");
+    }
+
+    #[test]
+    fn long_names_are_truncated_to_46_characters() {
+        let file_name = "Screenshot_2020-05-01-10-00-00_com.example.gallery.jpg";
+        assert_has_name(
+            file_name,
+            "Screenshot_2020-05-01-10-00-00_com.example.gal.json",
+        );
+        assert_has_name(
+            "Screenshot_2020-05-01-10-00-00_com.example.gallery(2).jpg",
+            "Screenshot_2020-05-01-10-00-00_com.example.gal(2).json",
+        );
+    }
+
+    #[test]
+    fn counter_moves_to_the_end() {
+        assert_eq!(
+            takeout_json_names("IMG_0001(1).jpg")[0],
+            "IMG_0001.jpg(1).json"
+        );
+        assert_has_name("IMG_0001(1).jpg", "IMG_0001(1).json");
+        assert!(
+            !takeout_json_names("IMG_0001(1).jpg").contains(&"IMG_0001(1).jpg.json".to_string())
+        );
+    }
+
+    #[test]
+    fn edited_photos_share_the_json_of_the_original() {
+        assert!(is_edited_photo_name("IMG_0001-edited.jpg"));
+        assert!(is_edited_photo_name("IMG_0001-edited(1).jpg"));
+        assert!(!is_edited_photo_name("IMG_0001.jpg"));
+        assert_eq!(
+            takeout_json_names("IMG_0001-edited.jpg"),
+            takeout_json_names("IMG_0001.jpg")
+        );
+        assert_has_name("IMG_0001-bearbeitet(1).jpg", "IMG_0001.jpg(1).json");
+    }
+
+    #[test]
+    fn supplemental_metadata_names_are_included() {
+        assert_has_name("IMG_0001.jpg", "IMG_0001.jpg.supplemental-metadata.json");
+        assert_has_name(
+            "IMG_0001(1).jpg",
+            "IMG_0001.jpg.supplemental-metadata(1).json",
+        );
+        assert_has_name(
+            "PXL_20210101_101010123.PORTRAIT.jpg",
+            "PXL_20210101_101010123.PORTRAIT.jpg.supplement.json",
+        );
+    }
+}
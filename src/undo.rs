@@ -0,0 +1,223 @@
+use crate::file_hash::*;
+use crate::file_move::*;
+use crate::journal::*;
+use std::{
+    cell::Cell,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[doc(hidden)]
+pub use anyhow::*;
+
+/// An event raised as the files of a run are moved back.
+pub enum UndoEvent<'a> {
+    /// Raised when a file is moved back to where it came from.
+    Restored { from: &'a Path, to: &'a Path },
+    /// Raised when a file is no longer where the run put it.
+    Missing { file: &'a Path },
+    /// Raised when a file was changed after the run moved it.
+    Changed { file: &'a Path },
+    /// Raised when a file cannot be moved back because another file is now in its original location.
+    Occupied { from: &'a Path, to: &'a Path },
+    /// Raised when a copy is removed because its original is still in place.
+    CopyRemoved { file: &'a Path, original: &'a Path },
+    /// Raised when a copy is kept because its original is no longer in place.
+    CopyKept { file: &'a Path, original: &'a Path },
+    /// Raised when there is an error moving a file back.
+    Failed {
+        file: &'a Path,
+        error: anyhow::Error,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct UndoResult {
+    pub undo_run_id: String,
+    pub files: u64,
+    pub files_restored: u64,
+    pub files_missing: u64,
+    pub files_changed: u64,
+    pub files_occupied: u64,
+    pub copies_removed: u64,
+    pub copies_kept: u64,
+    pub files_errored: u64,
+    pub duration: Duration,
+}
+
+/// Undoes a run by moving its files back to where they came from.
+///
+/// # Arguments
+///
+/// * `organized_dir` - The organized directory containing the journal.
+/// * `run_id` - The id of the run to undo.
+/// * `event_handler` - The handler for listening to events as undo progresses.
+///
+/// The journal entries of the run are replayed in reverse. A file is only moved back if it is still where the run
+/// put it, still has the same contents, and nothing else has been put in its original location. Photiso never
+/// overwrites a file, so anything else is reported and left alone.
+///
+/// A copied file is removed only if the original is still in place with the same contents; otherwise the copy
+/// is kept.
+///
+/// Each file moved back is appended to the journal under a new run id, so an undo can itself be undone.
+///
+/// If the `event_handler` returns true, undo continues; otherwise undo will stop processing files and return.
+pub fn undo<F>(organized_dir: &Path, run_id: &str, event_handler: F) -> anyhow::Result<UndoResult>
+where
+    F: Fn(UndoEvent) -> bool,
+{
+    let timer = Instant::now();
+
+    // a removed copy cannot be brought back, but its original was never touched
+    let entries: Vec<JournalEntry> = read_journal(organized_dir)?
+        .into_iter()
+        .filter(|e| e.run_id == run_id && e.kind != JournalKind::CopyRemoved)
+        .collect();
+
+    ensure!(
+        !entries.is_empty(),
+        "There are no changes for run '{}' in the journal {:?}.",
+        run_id,
+        get_journal_path(organized_dir)
+    );
+
+    let mut journal = Journal::open(organized_dir, &new_run_id())?;
+
+    let files = Cell::new(0);
+    let files_restored = Cell::new(0);
+    let files_missing = Cell::new(0);
+    let files_changed = Cell::new(0);
+    let files_occupied = Cell::new(0);
+    let copies_removed = Cell::new(0);
+    let copies_kept = Cell::new(0);
+    let files_errored = Cell::new(0);
+
+    for entry in entries.iter().rev() {
+        increment(&files);
+
+        let handled = match undo_entry(entry, &mut journal) {
+            Ok(UndoOutcome::Restored) => {
+                increment(&files_restored);
+                event_handler(UndoEvent::Restored {
+                    from: &entry.to,
+                    to: &entry.from,
+                })
+            }
+            Ok(UndoOutcome::Missing) => {
+                increment(&files_missing);
+                event_handler(UndoEvent::Missing { file: &entry.to })
+            }
+            Ok(UndoOutcome::Changed) => {
+                increment(&files_changed);
+                event_handler(UndoEvent::Changed { file: &entry.to })
+            }
+            Ok(UndoOutcome::Occupied) => {
+                increment(&files_occupied);
+                event_handler(UndoEvent::Occupied {
+                    from: &entry.to,
+                    to: &entry.from,
+                })
+            }
+            Ok(UndoOutcome::CopyRemoved) => {
+                increment(&copies_removed);
+                event_handler(UndoEvent::CopyRemoved {
+                    file: &entry.to,
+                    original: &entry.from,
+                })
+            }
+            Ok(UndoOutcome::CopyKept) => {
+                increment(&copies_kept);
+                event_handler(UndoEvent::CopyKept {
+                    file: &entry.to,
+                    original: &entry.from,
+                })
+            }
+            Err(error) => {
+                increment(&files_errored);
+                event_handler(UndoEvent::Failed {
+                    file: &entry.to,
+                    error,
+                })
+            }
+        };
+
+        if !handled {
+            break;
+        }
+    }
+
+    Ok(UndoResult {
+        undo_run_id: journal.run_id().to_string(),
+        files: files.get(),
+        files_restored: files_restored.get(),
+        files_missing: files_missing.get(),
+        files_changed: files_changed.get(),
+        files_occupied: files_occupied.get(),
+        copies_removed: copies_removed.get(),
+        copies_kept: copies_kept.get(),
+        files_errored: files_errored.get(),
+        duration: timer.elapsed(),
+    })
+}
+
+#[doc(hidden)]
+enum UndoOutcome {
+    Restored,
+    Missing,
+    Changed,
+    Occupied,
+    CopyRemoved,
+    CopyKept,
+}
+
+#[doc(hidden)]
+fn undo_entry(entry: &JournalEntry, journal: &mut Journal) -> anyhow::Result<UndoOutcome> {
+    if !entry.to.is_file() {
+        return Ok(UndoOutcome::Missing);
+    }
+
+    if get_file_hash(&entry.to)? != entry.hash {
+        return Ok(UndoOutcome::Changed);
+    }
+
+    if matches!(
+        entry.kind,
+        JournalKind::PhotoCopied | JournalKind::SidecarCopied
+    ) {
+        // only remove the copy when the original is still in place, so nothing is ever lost
+        if !entry.from.is_file() || get_file_hash(&entry.from)? != entry.hash {
+            return Ok(UndoOutcome::CopyKept);
+        }
+
+        fs::remove_file(&entry.to)?;
+        journal.append(
+            JournalKind::CopyRemoved,
+            &entry.to,
+            &entry.from,
+            &entry.hash,
+        )?;
+
+        return Ok(UndoOutcome::CopyRemoved);
+    }
+
+    // never overwrite a file, including one that appears while the file is moved back
+    if fs::symlink_metadata(&entry.from).is_ok() {
+        return Ok(UndoOutcome::Occupied);
+    }
+
+    match move_file_no_clobber(&entry.to, &entry.from) {
+        Ok(_) => {}
+        Err(err) if is_already_exists_error(&err) => return Ok(UndoOutcome::Occupied),
+        Err(err) => return Err(err),
+    }
+    journal.append(JournalKind::Undone, &entry.to, &entry.from, &entry.hash)?;
+
+    Ok(UndoOutcome::Restored)
+}
+
+#[doc(hidden)]
+fn increment(cell: &Cell<u64>) {
+    cell.set(cell.get() + 1)
+}
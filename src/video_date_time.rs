@@ -0,0 +1,252 @@
+use crate::bmff::*;
+use crate::photo_date_time::*;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::{fs::File, io::BufReader, path::Path};
+
+/// The key of the Apple QuickTime creation date, which includes the time zone (e.g. `2021-06-01T23:30:00+0900`).
+const APPLE_CREATION_DATE_KEY: &[u8] = b"com.apple.quicktime.creationdate";
+
+/// The QuickTime date atom (i.e. `©day`).
+const DAY_BOX_TYPE: &[u8; 4] = &[0xA9, b'd', b'a', b'y'];
+
+/// The most bytes read from a metadata box. Metadata is small, so anything larger is corrupt.
+const MAX_METADATA_SIZE: u64 = 1024 * 1024;
+
+/// Reads the date-time a QuickTime or MP4 video was recorded, or None if the file is not a video or has no date.
+///
+/// This prefers the Apple creation date, then the `©day` date (both are local time), then the `mvhd` creation time
+/// (which is UTC).
+pub fn read_video_date_time(file_path: &Path) -> anyhow::Result<Option<PhotoDateTime>> {
+    Ok(read_video_date(file_path)?.map(|(_, _, date_time)| date_time))
+}
+
+/// Reads the atom a video's date-time is read from and its raw value (e.g. the seconds of the `mvhd` creation time).
+pub fn read_video_raw_values(file_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    Ok(read_video_date(file_path)?
+        .map(|(atom, value, _)| (atom.to_string(), value))
+        .into_iter()
+        .collect())
+}
+
+/// Reads the date-time a video was recorded, along with the atom it was read from and its raw value.
+#[doc(hidden)]
+fn read_video_date(
+    file_path: &Path,
+) -> anyhow::Result<Option<(&'static str, String, PhotoDateTime)>> {
+    let file = File::open(file_path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    if !is_bmff(&mut reader)? {
+        return Ok(None);
+    }
+
+    let moov = match find_box(&mut reader, 0, len, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(None),
+    };
+
+    if let Some((value, date_time)) = read_apple_creation_date(&mut reader, &moov)? {
+        return Ok(Some(("com.apple.quicktime.creationdate", value, date_time)));
+    }
+
+    if let Some((value, date_time)) = read_day(&mut reader, &moov)? {
+        return Ok(Some(("©day", value, date_time)));
+    }
+
+    Ok(read_movie_header_creation_time(&mut reader, &moov)?
+        .map(|(value, date_time)| ("mvhd", value, date_time)))
+}
+
+/// Reads `moov/meta/keys` and `moov/meta/ilst` for the Apple creation date.
+#[doc(hidden)]
+fn read_apple_creation_date(
+    reader: &mut BufReader<File>,
+    moov: &BmffBox,
+) -> anyhow::Result<Option<(String, PhotoDateTime)>> {
+    let meta = match find_box_path(reader, moov, &[b"meta"])? {
+        Some(meta) => skip_full_box_header(reader, meta)?,
+        None => return Ok(None),
+    };
+
+    let keys = match find_box_path(reader, &meta, &[b"keys"])? {
+        Some(keys) => read_box_contents(reader, &keys, MAX_METADATA_SIZE)?,
+        None => return Ok(None),
+    };
+
+    // keys are version/flags, a count, and then (size, namespace, name) entries; the index of a key is 1 based
+    let mut key_index = None;
+    let mut position = 8;
+    let mut index = 1u32;
+    while position + 8 <= keys.len() {
+        let size = u32::from_be_bytes([
+            keys[position],
+            keys[position + 1],
+            keys[position + 2],
+            keys[position + 3],
+        ]) as usize;
+        if size < 8 || position + size > keys.len() {
+            break;
+        }
+
+        if &keys[position + 8..position + size] == APPLE_CREATION_DATE_KEY {
+            key_index = Some(index);
+            break;
+        }
+
+        position += size;
+        index += 1;
+    }
+
+    let key_index = match key_index {
+        Some(key_index) => key_index,
+        None => return Ok(None),
+    };
+
+    // the items in ilst have the key index as their type
+    let item = match find_box_path(reader, &meta, &[b"ilst", &key_index.to_be_bytes()])? {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+
+    Ok(read_data_box_text(reader, &item)?.and_then(parse_raw_video_date_time))
+}
+
+/// Reads the `©day` date, which is in `moov/udta` as a QuickTime string or in an iTunes style `ilst`.
+#[doc(hidden)]
+fn read_day(
+    reader: &mut BufReader<File>,
+    moov: &BmffBox,
+) -> anyhow::Result<Option<(String, PhotoDateTime)>> {
+    let udta = match find_box_path(reader, moov, &[b"udta"])? {
+        Some(udta) => udta,
+        None => return Ok(None),
+    };
+
+    // QuickTime string: a 16-bit length, a 16-bit language, and then the text
+    if let Some(day) = find_box_path(reader, &udta, &[DAY_BOX_TYPE])? {
+        let contents = read_box_contents(reader, &day, MAX_METADATA_SIZE)?;
+        if contents.len() > 4 {
+            let len = u16::from_be_bytes([contents[0], contents[1]]) as usize;
+            let text = &contents[4..(4 + len).min(contents.len())];
+            return Ok(parse_raw_video_date_time(
+                String::from_utf8_lossy(text).to_string(),
+            ));
+        }
+    }
+
+    // iTunes style metadata: meta/ilst/©day/data
+    if let Some(meta) = find_box_path(reader, &udta, &[b"meta"])? {
+        let meta = skip_full_box_header(reader, meta)?;
+        if let Some(day) = find_box_path(reader, &meta, &[b"ilst", DAY_BOX_TYPE])? {
+            return Ok(read_data_box_text(reader, &day)?.and_then(parse_raw_video_date_time));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the creation time in `moov/mvhd`, which is the number of seconds since 1904-01-01 in UTC.
+#[doc(hidden)]
+fn read_movie_header_creation_time(
+    reader: &mut BufReader<File>,
+    moov: &BmffBox,
+) -> anyhow::Result<Option<(String, PhotoDateTime)>> {
+    let mvhd = match find_box_path(reader, moov, &[b"mvhd"])? {
+        Some(mvhd) => read_box_contents(reader, &mvhd, 16)?,
+        None => return Ok(None),
+    };
+
+    // version 0 has a 32-bit creation time and version 1 has a 64-bit creation time, after version/flags
+    let seconds = match (mvhd.first(), mvhd.len()) {
+        (Some(0), len) if len >= 8 => {
+            u32::from_be_bytes([mvhd[4], mvhd[5], mvhd[6], mvhd[7]]) as i64
+        }
+        (Some(1), len) if len >= 12 => i64::from_be_bytes([
+            mvhd[4], mvhd[5], mvhd[6], mvhd[7], mvhd[8], mvhd[9], mvhd[10], mvhd[11],
+        ]),
+        _ => return Ok(None),
+    };
+
+    // a creation time of 0 means it was not set
+    if seconds <= 0 {
+        return Ok(None);
+    }
+
+    let epoch = NaiveDate::from_ymd(1904, 1, 1).and_hms(0, 0, 0);
+    let utc = epoch.checked_add_signed(Duration::seconds(seconds));
+
+    Ok(utc.map(|utc| (seconds.to_string(), PhotoDateTime::from_naive_utc(&utc))))
+}
+
+/// Reads the text of the `data` box in an `ilst` item: a 32-bit type, a 32-bit locale, and then the value.
+#[doc(hidden)]
+fn read_data_box_text(
+    reader: &mut BufReader<File>,
+    item: &BmffBox,
+) -> anyhow::Result<Option<String>> {
+    let data = match find_box_path(reader, item, &[b"data"])? {
+        Some(data) => read_box_contents(reader, &data, MAX_METADATA_SIZE)?,
+        None => return Ok(None),
+    };
+
+    if data.len() <= 8 {
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&data[8..]).to_string()))
+}
+
+/// A `meta` box is a full box (i.e. it starts with version/flags) in MP4 files, but not in QuickTime files.
+#[doc(hidden)]
+fn skip_full_box_header(reader: &mut BufReader<File>, meta: BmffBox) -> anyhow::Result<BmffBox> {
+    let header = read_box_contents(reader, &meta, 4)?;
+    if header == [0, 0, 0, 0] {
+        return Ok(BmffBox {
+            box_type: meta.box_type,
+            offset: meta.offset + 4,
+            size: meta.size - 4,
+        });
+    }
+
+    Ok(meta)
+}
+
+/// Parses a video date-time, keeping the text it was parsed from.
+#[doc(hidden)]
+fn parse_raw_video_date_time(text: String) -> Option<(String, PhotoDateTime)> {
+    parse_video_date_time(&text).map(|date_time| (text, date_time))
+}
+
+/// Parses a video date-time (e.g. `2021-06-01T23:30:00+0900`, `2021-06-01T23:30:00Z`, or `2021-06-01 23:30:00`).
+/// Dates without a time are not precise enough to use.
+#[doc(hidden)]
+fn parse_video_date_time(text: &str) -> Option<PhotoDateTime> {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+
+    for format in &["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%dT%H:%M:%S%.f%:z"] {
+        if let Ok(date_time) = chrono::DateTime::parse_from_str(text, format) {
+            return Some(PhotoDateTime {
+                local: date_time.naive_local(),
+                offset: Some(*date_time.offset()),
+            });
+        }
+    }
+
+    if let Some(text) = text.strip_suffix('Z') {
+        if let Ok(utc) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f") {
+            return Some(PhotoDateTime::from_naive_utc(&utc));
+        }
+    }
+
+    for format in &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(local) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(PhotoDateTime {
+                local,
+                offset: None,
+            });
+        }
+    }
+
+    None
+}
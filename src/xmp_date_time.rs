@@ -0,0 +1,373 @@
+use crate::photo_date_time::*;
+use chrono::NaiveDateTime;
+use regex::Regex;
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The TIFF tag (in IFD0) that contains the XMP packet of TIFF files and TIFF based RAW files (e.g. DNG).
+const TIFF_XMP_TAG: u16 = 700;
+
+/// The namespace at the start of a JPEG APP1 segment that contains an XMP packet.
+const JPEG_XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// The keyword of a PNG iTXt chunk that contains an XMP packet.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+/// The signature at the start of a PNG file.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The most bytes read from an XMP packet or sidecar. XMP is small, so anything larger is corrupt.
+const MAX_XMP_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The XMP properties for when a photo was taken, most precise first.
+///
+/// * `exif:DateTimeOriginal` is a copy of the EXIF `DateTimeOriginal` tag.
+/// * `photoshop:DateCreated` is when the content was created, which may only be a date.
+/// * `xmp:CreateDate` is when the file was created, which is when a camera took the photo.
+const XMP_DATE_PROPERTIES: &[&str] = &[
+    "exif:DateTimeOriginal",
+    "photoshop:DateCreated",
+    "xmp:CreateDate",
+];
+
+/// Reads the date-time a photo was taken from the XMP packet embedded in it, or None if the photo does not have
+/// one or it does not have a date-time.
+///
+/// The packet is in IFD0 of TIFF files (read with the EXIF information), an APP1 segment of JPEG files, an iTXt
+/// chunk of PNG files, or the `XMP ` chunk of WebP files.
+pub fn read_xmp_date_time(
+    file_path: &Path,
+    exif: Option<&exif::Exif>,
+) -> anyhow::Result<Option<PhotoDateTime>> {
+    let packet = read_xmp_packet(file_path, exif)?;
+    Ok(packet.and_then(|packet| parse_xmp_date_time(&String::from_utf8_lossy(&packet))))
+}
+
+/// Reads the date properties of the XMP packet embedded in a photo and their raw values, in the order they are in
+/// the packet.
+pub fn read_xmp_raw_values(
+    file_path: &Path,
+    exif: Option<&exif::Exif>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let packet = read_xmp_packet(file_path, exif)?;
+    Ok(packet
+        .map(|packet| get_xmp_date_properties(&String::from_utf8_lossy(&packet)))
+        .unwrap_or_default())
+}
+
+/// Reads the date-time a photo was taken from its `.xmp` sidecar file, or None if the photo does not have one or
+/// it does not have a date-time.
+pub fn read_xmp_sidecar_date_time(file_path: &Path) -> anyhow::Result<Option<PhotoDateTime>> {
+    let xmp = match find_xmp_sidecar(file_path) {
+        Some(sidecar_path) => read_xmp_sidecar(&sidecar_path)?,
+        None => return Ok(None),
+    };
+
+    Ok(parse_xmp_date_time(&String::from_utf8_lossy(&xmp)))
+}
+
+/// Reads the path of a photo's `.xmp` sidecar file, followed by its date properties and their raw values.
+pub fn read_xmp_sidecar_raw_values(file_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let sidecar_path = match find_xmp_sidecar(file_path) {
+        Some(sidecar_path) => sidecar_path,
+        None => return Ok(Vec::new()),
+    };
+
+    let xmp = read_xmp_sidecar(&sidecar_path)?;
+    let mut values = vec![("XmpSidecar".to_string(), sidecar_path.display().to_string())];
+    values.extend(get_xmp_date_properties(&String::from_utf8_lossy(&xmp)));
+    Ok(values)
+}
+
+/// TIFF files have the XMP packet with the EXIF information; other files have it embedded in their own format.
+#[doc(hidden)]
+fn read_xmp_packet(file_path: &Path, exif: Option<&exif::Exif>) -> anyhow::Result<Option<Vec<u8>>> {
+    match exif.and_then(read_tiff_xmp) {
+        Some(packet) => Ok(Some(packet)),
+        None => read_embedded_xmp(file_path),
+    }
+}
+
+#[doc(hidden)]
+fn find_xmp_sidecar(file_path: &Path) -> Option<PathBuf> {
+    xmp_sidecar_paths(file_path)
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+#[doc(hidden)]
+fn read_xmp_sidecar(sidecar_path: &Path) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        fs::metadata(sidecar_path)?.len() <= MAX_XMP_SIZE,
+        "The XMP sidecar '{}' is too large.",
+        sidecar_path.display()
+    );
+
+    Ok(fs::read(sidecar_path)?)
+}
+
+/// The paths of the sidecar files a photo may have: named after the photo (e.g. `IMG_0001.xmp`) or after its whole
+/// file name (e.g. `IMG_0001.CR2.xmp`), with either case of extension.
+#[doc(hidden)]
+fn xmp_sidecar_paths(file_path: &Path) -> Vec<PathBuf> {
+    let (dir, stem, file_name) = match (
+        file_path.parent(),
+        file_path.file_stem(),
+        file_path.file_name(),
+    ) {
+        (Some(dir), Some(stem), Some(file_name)) => (dir, stem, file_name),
+        _ => return Vec::new(),
+    };
+
+    let mut paths = Vec::new();
+    for name in [stem, file_name].iter() {
+        for extension in [".xmp", ".XMP"].iter() {
+            let mut sidecar_name = name.to_os_string();
+            sidecar_name.push(extension);
+            paths.push(dir.join(sidecar_name));
+        }
+    }
+
+    paths
+}
+
+// -------------------- Embedded XMP packets -------------------- //
+
+/// TIFF files have the XMP packet in IFD0, which is read with the rest of the EXIF information.
+#[doc(hidden)]
+fn read_tiff_xmp(exif: &exif::Exif) -> Option<Vec<u8>> {
+    let field = exif
+        .fields()
+        .find(|field| field.tag.number() == TIFF_XMP_TAG && field.ifd_num == exif::In::PRIMARY)?;
+
+    match &field.value {
+        exif::Value::Byte(packet) | exif::Value::Undefined(packet, _) => Some(packet.clone()),
+        _ => None,
+    }
+}
+
+/// Reads the XMP packet of a JPEG, PNG, or WebP file, or None if the file is not one of these or has no packet.
+#[doc(hidden)]
+fn read_embedded_xmp(file_path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut reader = BufReader::new(File::open(file_path)?);
+
+    let mut header = [0u8; 12];
+    if reader.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(0))?;
+
+    match &header {
+        _ if header.starts_with(&[0xFF, 0xD8]) => read_jpeg_xmp(&mut reader),
+        _ if header.starts_with(PNG_SIGNATURE) => read_png_xmp(&mut reader),
+        _ if &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" => read_webp_xmp(&mut reader),
+        _ => Ok(None),
+    }
+}
+
+/// JPEG files are segments of a marker (`FF xx`) and a 16-bit big endian length (which includes itself). The XMP
+/// packet is in an APP1 segment that starts with the XMP namespace. The metadata segments are before the image
+/// data, which starts at the SOS segment.
+#[doc(hidden)]
+fn read_jpeg_xmp(reader: &mut BufReader<File>) -> anyhow::Result<Option<Vec<u8>>> {
+    reader.seek(SeekFrom::Start(2))?;
+
+    loop {
+        let mut marker = [0u8; 4];
+        if reader.read_exact(&mut marker).is_err() || marker[0] != 0xFF {
+            return Ok(None);
+        }
+
+        let len = u16::from_be_bytes([marker[2], marker[3]]) as u64;
+        match marker[1] {
+            // start of scan or end of image
+            0xDA | 0xD9 => return Ok(None),
+            0xE1 if len >= 2 => {
+                let mut segment = Vec::new();
+                reader.by_ref().take(len - 2).read_to_end(&mut segment)?;
+                if let Some(packet) = segment.strip_prefix(JPEG_XMP_NAMESPACE) {
+                    return Ok(Some(packet.to_vec()));
+                }
+            }
+            _ if len >= 2 => {
+                reader.seek(SeekFrom::Current(len as i64 - 2))?;
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// PNG files are chunks of a 32-bit big endian length, a type, the data, and a CRC. The XMP packet is in an iTXt
+/// chunk: the keyword, a compression flag, a compression method, a language, a translated keyword, and then the
+/// text. XMP packets are not compressed, so compressed chunks are ignored.
+#[doc(hidden)]
+fn read_png_xmp(reader: &mut BufReader<File>) -> anyhow::Result<Option<Vec<u8>>> {
+    reader.seek(SeekFrom::Start(PNG_SIGNATURE.len() as u64))?;
+
+    loop {
+        let mut chunk_header = [0u8; 8];
+        if reader.read_exact(&mut chunk_header).is_err() {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes([
+            chunk_header[0],
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+        ]) as u64;
+        match &chunk_header[4..8] {
+            b"IEND" => return Ok(None),
+            b"iTXt" if len <= MAX_XMP_SIZE => {
+                let mut data = Vec::new();
+                reader.by_ref().take(len).read_to_end(&mut data)?;
+                if let Some(text) = parse_png_xmp_text(&data) {
+                    return Ok(Some(text.to_vec()));
+                }
+                reader.seek(SeekFrom::Current(4))?;
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64 + 4))?;
+            }
+        }
+    }
+}
+
+/// The text of an iTXt chunk, if it is an uncompressed XMP packet.
+#[doc(hidden)]
+fn parse_png_xmp_text(data: &[u8]) -> Option<&[u8]> {
+    let rest = data.strip_prefix(PNG_XMP_KEYWORD)?.strip_prefix(b"\0")?;
+
+    // the compression flag and method
+    if rest.first() != Some(&0) {
+        return None;
+    }
+    let rest = rest.get(2..)?;
+
+    // the language and the translated keyword
+    let language_end = rest.iter().position(|b| *b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|b| *b == 0)?;
+
+    Some(&rest[translated_end + 1..])
+}
+
+/// WebP files are RIFF files: chunks of a type, a 32-bit little endian length, and the data (padded to an even
+/// length). The XMP packet is in the `XMP ` chunk.
+#[doc(hidden)]
+fn read_webp_xmp(reader: &mut BufReader<File>) -> anyhow::Result<Option<Vec<u8>>> {
+    reader.seek(SeekFrom::Start(12))?;
+
+    loop {
+        let mut chunk_header = [0u8; 8];
+        if reader.read_exact(&mut chunk_header).is_err() {
+            return Ok(None);
+        }
+
+        let len = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as u64;
+        if &chunk_header[0..4] == b"XMP " && len <= MAX_XMP_SIZE {
+            let mut packet = Vec::new();
+            reader.by_ref().take(len).read_to_end(&mut packet)?;
+            return Ok(Some(packet));
+        }
+
+        reader.seek(SeekFrom::Current((len + len % 2) as i64))?;
+    }
+}
+
+// -------------------- XMP -> PhotoDateTime conversion -------------------- //
+
+/// Finds the date-time a photo was taken in an XMP packet, from the first property in `XMP_DATE_PROPERTIES` that
+/// has a date with a time.
+#[doc(hidden)]
+fn parse_xmp_date_time(xmp: &str) -> Option<PhotoDateTime> {
+    let values: Vec<(String, PhotoDateTime)> = get_xmp_date_properties(xmp)
+        .into_iter()
+        .filter_map(|(name, value)| parse_xmp_date(&value).map(|date_time| (name, date_time)))
+        .collect();
+
+    XMP_DATE_PROPERTIES.iter().find_map(|property| {
+        values
+            .iter()
+            .find(|(name, _)| name == property)
+            .map(|(_, date_time)| *date_time)
+    })
+}
+
+/// Finds the properties in `XMP_DATE_PROPERTIES` in an XMP packet, with their raw values. A property is either an
+/// attribute (`xmp:CreateDate="..."`) or an element (`<xmp:CreateDate>...</xmp:CreateDate>`).
+#[doc(hidden)]
+fn get_xmp_date_properties(xmp: &str) -> Vec<(String, String)> {
+    static PROPERTY_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = PROPERTY_REGEX.get_or_init(|| {
+        let names = XMP_DATE_PROPERTIES
+            .iter()
+            .map(|name| regex::escape(name))
+            .collect::<Vec<_>>()
+            .join("|");
+        Regex::new(&format!(
+            r#"[\s<](?P<name>{})\s*(?:=\s*(?:"(?P<double>[^"]*)"|'(?P<single>[^']*)')|>(?P<element>[^<]*)</)"#,
+            names
+        ))
+        .unwrap()
+    });
+
+    regex
+        .captures_iter(xmp)
+        .filter_map(|captures| {
+            let value = captures
+                .name("double")
+                .or_else(|| captures.name("single"))
+                .or_else(|| captures.name("element"))?;
+            Some((
+                captures.name("name")?.as_str().to_string(),
+                value.as_str().to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Parses an XMP date (ISO 8601, e.g. `2021-06-01T23:30:00.123+09:00`, `2021-06-01T14:30:00Z`, or
+/// `2021-06-01T23:30`). Dates without a time are not precise enough to use.
+#[doc(hidden)]
+fn parse_xmp_date(text: &str) -> Option<PhotoDateTime> {
+    let text = text.trim();
+
+    for format in &["%Y-%m-%dT%H:%M:%S%.f%:z", "%Y-%m-%dT%H:%M%:z"] {
+        if let Ok(date_time) = DateTime::parse_from_str(text, format) {
+            return Some(PhotoDateTime {
+                local: date_time.naive_local(),
+                offset: Some(*date_time.offset()),
+            });
+        }
+    }
+
+    let (text, utc) = match text.strip_suffix('Z') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+
+    for format in &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"] {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(text, format) {
+            if utc {
+                return Some(PhotoDateTime::from_naive_utc(&date_time));
+            }
+
+            return Some(PhotoDateTime {
+                local: date_time,
+                offset: None,
+            });
+        }
+    }
+
+    None
+}